@@ -6,21 +6,9 @@ use serde_json::Value;
 use tokio::sync::{Mutex, mpsc};
 
 use crate::core::api::{AnthropicClient, ContentBlock, Message, MessageContent};
+use crate::core::config::Permissions;
 use crate::core::tools;
 
-/// System prompt 由多模块在编译期拼接而成，见 `src/core/prompt/*.md`。
-pub const SYSTEM_PROMPT: &str = concat!(
-    include_str!("prompt/identity.md"),
-    "\n\n",
-    include_str!("prompt/tools_general.md"),
-    "\n\n",
-    include_str!("prompt/tools_specialized.md"),
-    "\n\n",
-    include_str!("prompt/work_style.md"),
-    "\n\n",
-    include_str!("prompt/response_format.md"),
-);
-
 /// Events emitted by the agent loop in real time.
 #[derive(Debug, Clone)]
 pub enum AgentEvent {
@@ -37,6 +25,7 @@ pub async fn run_agent_loop(
     tx: mpsc::UnboundedSender<AgentEvent>,
     _task_file: &PathBuf,
     pending_user_messages: &Arc<tokio::sync::Mutex<Vec<String>>>,
+    permissions: &Permissions,
 ) -> Result<()> {
     loop {
         let snapshot = messages.lock().await.clone();
@@ -47,12 +36,10 @@ pub async fn run_agent_loop(
         let mut tool_calls = Vec::new();
         for block in &response.content {
             match block {
-                ContentBlock::Text { text } => {
-                    if !text.is_empty() {
-                        full_text.push_str(text);
-                        for line in text.lines() {
-                            let _ = tx.send(AgentEvent::Text(line.to_string()));
-                        }
+                ContentBlock::Text { text } if !text.is_empty() => {
+                    full_text.push_str(text);
+                    for line in text.lines() {
+                        let _ = tx.send(AgentEvent::Text(line.to_string()));
                     }
                 }
                 ContentBlock::ToolUse { id, name, input } => {
@@ -84,9 +71,15 @@ pub async fn run_agent_loop(
 
         let mut results = Vec::new();
         for (id, name, input) in &tool_calls {
-            let (content, is_error) = match tools::execute(name, input) {
-                Ok(output) => (output, None),
-                Err(e) => (e.to_string(), Some(true)),
+            // Run on the blocking pool: a command may curl the MCP bridge, which is
+            // served by this same runtime (possibly a single worker thread).
+            let (name, input, perms) = (name.clone(), input.clone(), permissions.clone());
+            let outcome =
+                tokio::task::spawn_blocking(move || tools::execute(&name, &input, &perms)).await;
+            let (content, is_error) = match outcome {
+                Ok(Ok(output)) => (output, None),
+                Ok(Err(e)) => (e.to_string(), Some(true)),
+                Err(e) => (format!("tool execution panicked: {e}"), Some(true)),
             };
 
             let preview = content.lines().next().unwrap_or("(empty)").to_string();
@@ -1,7 +1,9 @@
-use anyhow::{Result, bail};
-use reqwest::Client;
+use anyhow::Result;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 use crate::core::config::{API_VERSION, ApiConfig};
 
@@ -53,50 +55,301 @@ pub struct Response {
     pub stop_reason: Option<String>,
 }
 
+/// Retry and failover tuning for [`AnthropicClient`].
+#[derive(Debug, Clone)]
+pub struct FailoverPolicy {
+    /// Extra attempts on the same provider after a retryable failure.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled on each further attempt.
+    pub retry_delay: Duration,
+    /// How long to stay on a fallback before trying the primary again.
+    pub cooldown: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            retry_delay: Duration::from_secs(1),
+            cooldown: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Provider switches reported to the UI through [`AnthropicClient::with_event_handler`].
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    /// `from` exhausted its retries; requests now go to `to` (both `provider/model`).
+    Failover {
+        from: String,
+        to: String,
+        error: String,
+    },
+    /// The cool-down elapsed and the primary answered again.
+    Restored { primary: String },
+}
+
+type EventHandler = Box<dyn Fn(ClientEvent) + Send + Sync>;
+
+/// What one request changes from the client's defaults.
+#[derive(Clone, Copy, Default)]
+struct RequestOptions<'a> {
+    /// Replaces the session's system prompt.
+    system: Option<&'a str>,
+    /// Caps the `max_tokens` of whichever provider answers.
+    max_tokens: Option<u32>,
+}
+
+/// Which entry of the chain (0 = primary) is serving requests, and since when.
+struct ActiveProvider {
+    index: usize,
+    since: Instant,
+}
+
+/// Outcome of a single HTTP attempt: retryable failures move down the chain,
+/// fatal ones (e.g. a 400 for a malformed request) are returned as-is.
+enum AttemptError {
+    Retryable(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
 pub struct AnthropicClient {
     client: Client,
-    config: ApiConfig,
-    system: String,
+    /// Swappable at runtime by `/model`; takes effect from the next request.
+    config: RwLock<ApiConfig>,
+    /// Tried in order once the primary has exhausted its retries.
+    fallbacks: Vec<ApiConfig>,
+    policy: FailoverPolicy,
+    active: Mutex<ActiveProvider>,
+    on_event: Option<EventHandler>,
+    /// Regenerated when MCP servers change their tool lists.
+    system: RwLock<String>,
 }
 
 impl AnthropicClient {
     pub fn new(config: ApiConfig, system: String) -> Self {
         Self {
             client: Client::new(),
-            config,
-            system,
+            config: RwLock::new(config),
+            fallbacks: Vec::new(),
+            policy: FailoverPolicy::default(),
+            active: Mutex::new(ActiveProvider {
+                index: 0,
+                since: Instant::now(),
+            }),
+            on_event: None,
+            system: RwLock::new(system),
+        }
+    }
+
+    pub fn with_fallbacks(mut self, fallbacks: Vec<ApiConfig>) -> Self {
+        self.fallbacks = fallbacks;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: FailoverPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_event_handler(
+        mut self,
+        handler: impl Fn(ClientEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_event = Some(Box::new(handler));
+        self
+    }
+
+    /// Snapshot of the primary provider/model configuration.
+    pub fn config(&self) -> ApiConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replace the primary and go back to it immediately.
+    pub fn set_config(&self, config: ApiConfig) {
+        *self.config.write().unwrap() = config;
+        self.active.lock().unwrap().index = 0;
+    }
+
+    /// Replace the system prompt; takes effect from the next request.
+    pub fn set_system(&self, system: String) {
+        *self.system.write().unwrap() = system;
+    }
+
+    /// The configuration currently serving requests (the primary unless failed over).
+    pub fn active_config(&self) -> ApiConfig {
+        let index = self.active.lock().unwrap().index;
+        match index {
+            0 => self.config(),
+            i => self.fallbacks[i - 1].clone(),
+        }
+    }
+
+    fn emit(&self, event: ClientEvent) {
+        if let Some(handler) = &self.on_event {
+            handler(event);
         }
     }
 
+    /// Send a request, retrying the active provider and then walking the fallback
+    /// chain, wrapping around to the entries before it. After `policy.cooldown` on a
+    /// fallback the primary is tried first again. `Failover` / `Restored` are only
+    /// emitted when a different entry ends up serving the request.
     pub async fn send(&self, messages: &[Message], tools: &[Value]) -> Result<Response> {
+        let (response, _) = self
+            .send_through_chain(messages, tools, RequestOptions::default())
+            .await?;
+        Ok(response)
+    }
+
+    /// A request on behalf of an MCP server (`sampling/createMessage`): its own
+    /// system prompt and at most `max_tokens`, through the same retries and
+    /// fallback chain as the session. Returns the configuration that answered.
+    pub async fn sample(
+        &self,
+        system: &str,
+        max_tokens: u32,
+        messages: &[Message],
+    ) -> Result<(Response, ApiConfig)> {
+        let options = RequestOptions {
+            system: Some(system),
+            max_tokens: Some(max_tokens),
+        };
+        self.send_through_chain(messages, &[], options).await
+    }
+
+    async fn send_through_chain(
+        &self,
+        messages: &[Message],
+        tools: &[Value],
+        options: RequestOptions<'_>,
+    ) -> Result<(Response, ApiConfig)> {
+        let mut chain = vec![self.config()];
+        chain.extend(self.fallbacks.iter().cloned());
+
+        let start = {
+            let active = self.active.lock().unwrap();
+            if active.index > 0 && active.since.elapsed() >= self.policy.cooldown {
+                0
+            } else {
+                active.index
+            }
+        };
+        let previous = self.active.lock().unwrap().index;
+        if let Some(max_tokens) = options.max_tokens {
+            for config in &mut chain {
+                config.max_tokens = config.max_tokens.min(max_tokens);
+            }
+        }
+
+        // The last entry that failed, and its error.
+        let mut last_error: Option<(usize, anyhow::Error)> = None;
+        for index in (start..chain.len()).chain(0..start) {
+            let config = &chain[index];
+            match self
+                .send_with_retries(config, messages, tools, options)
+                .await
+            {
+                Ok(response) => {
+                    if index != previous || start != previous {
+                        *self.active.lock().unwrap() = ActiveProvider {
+                            index,
+                            since: Instant::now(),
+                        };
+                    }
+                    match last_error {
+                        _ if index == previous => {}
+                        Some((from, error)) if index > 0 => self.emit(ClientEvent::Failover {
+                            from: chain[from].label(),
+                            to: config.label(),
+                            error: format!("{error}"),
+                        }),
+                        _ => self.emit(ClientEvent::Restored {
+                            primary: config.label(),
+                        }),
+                    }
+                    return Ok((response, config.clone()));
+                }
+                Err(AttemptError::Fatal(e)) => return Err(e),
+                Err(AttemptError::Retryable(e)) => last_error = Some((index, e)),
+            }
+        }
+        Err(last_error
+            .map(|(_, e)| e)
+            .unwrap_or_else(|| anyhow::anyhow!("no model provider available")))
+    }
+
+    async fn send_with_retries(
+        &self,
+        config: &ApiConfig,
+        messages: &[Message],
+        tools: &[Value],
+        options: RequestOptions<'_>,
+    ) -> Result<Response, AttemptError> {
+        let mut delay = self.policy.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match self.send_once(config, messages, tools, options).await {
+                Err(AttemptError::Retryable(_)) if attempt < self.policy.max_retries => {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn send_once(
+        &self,
+        config: &ApiConfig,
+        messages: &[Message],
+        tools: &[Value],
+        options: RequestOptions<'_>,
+    ) -> Result<Response, AttemptError> {
+        let system = match options.system {
+            Some(system) => system.to_string(),
+            None => self.system.read().unwrap().clone(),
+        };
         let req = Request {
-            model: self.config.model.clone(),
-            system: self.system.clone(),
-            max_tokens: self.config.max_tokens,
+            model: config.model.clone(),
+            system,
+            max_tokens: config.max_tokens,
             messages: messages.to_vec(),
             tools: tools.to_vec(),
         };
 
-        let url = format!("{}/v1/messages", self.config.base_url.trim_end_matches('/'));
+        let url = format!("{}/v1/messages", config.base_url.trim_end_matches('/'));
 
         let resp = self
             .client
             .post(&url)
-            .header("x-api-key", &self.config.api_key)
+            .header("x-api-key", &config.api_key)
             .header("anthropic-version", API_VERSION)
             .header("content-type", "application/json")
             .json(&req)
             .send()
-            .await?;
+            .await
+            .map_err(|e| AttemptError::Retryable(e.into()))?;
 
         let status = resp.status();
-        let body = resp.text().await?;
+        let body = resp
+            .text()
+            .await
+            .map_err(|e| AttemptError::Retryable(e.into()))?;
 
         if !status.is_success() {
-            bail!("API error ({}): {}", status, body);
+            let error = anyhow::anyhow!("API error ({}): {}", status, body);
+            let retryable = status.is_server_error()
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || status == StatusCode::REQUEST_TIMEOUT;
+            return Err(if retryable {
+                AttemptError::Retryable(error)
+            } else {
+                AttemptError::Fatal(error)
+            });
         }
 
-        let response: Response = serde_json::from_str(&body)?;
-        Ok(response)
+        serde_json::from_str(&body).map_err(|e| AttemptError::Fatal(e.into()))
     }
 }
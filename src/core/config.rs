@@ -1,10 +1,20 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use serde::Deserialize;
-use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::core::api::FailoverPolicy;
 
 pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
 pub const API_VERSION: &str = "2023-06-01";
 pub const DEFAULT_MAX_TOKENS: u32 = 131072;
+pub const DEFAULT_MODEL: &str = "claude-sonnet-4-20250514";
+/// Model for a non-Anthropic provider configured without `model`.
+pub const DEFAULT_PROVIDER_MODEL: &str = "deepseek-chat";
+pub const DEFAULT_MCP_COMPACT_THRESHOLD: usize = 20;
 
 /// One model provider in settings.json (e.g. deepseek, openai).
 #[derive(Debug, Clone, Deserialize)]
@@ -12,91 +22,789 @@ pub struct ModelProvider {
     pub name: String,
     #[serde(default)]
     pub base_url: String,
+    /// May reference environment variables as `${VAR}` or `${VAR:-default}`.
     #[serde(default)]
     pub api_key: String,
+    /// Shell command whose trimmed stdout is used as the key when `api_key` is empty,
+    /// e.g. `pass show deepseek`.
+    #[serde(default)]
+    pub api_key_command: Option<String>,
+    /// Models offered by this provider in the `/model` menu.
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+/// Bash permission rules: glob patterns (`*` wildcard) matched against the whole command.
+/// A command matching a `deny` rule is refused unless it also matches an `allow` rule.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Permissions {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
 }
 
-/// Contents of ~/.mash/settings.json
+/// Keys accepted in settings.json, used for unknown-key warnings.
+const SETTINGS_KEYS: [&str; 10] = [
+    "model_provider",
+    "model",
+    "max_tokens",
+    "model_providers",
+    "permissions",
+    "fallback",
+    "max_retries",
+    "failover_cooldown_secs",
+    "mcp_compact_threshold",
+    "trusted_projects",
+];
+const PROVIDER_KEYS: [&str; 5] = ["name", "base_url", "api_key", "api_key_command", "models"];
+const PERMISSION_KEYS: [&str; 2] = ["allow", "deny"];
+
+/// One entry of the `fallback` chain, tried in order when the primary is down.
 #[derive(Debug, Clone, Deserialize)]
+pub struct FallbackTarget {
+    pub provider: String,
+    #[serde(default)]
+    pub model: String,
+}
+
+/// Effective settings after merging every layer (see [`Settings::load`]).
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Settings {
     #[serde(default)]
     pub model_provider: String,
     #[serde(default)]
     pub model: String,
     #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
     pub model_providers: Vec<ModelProvider>,
+    #[serde(default)]
+    pub permissions: Permissions,
+    #[serde(default)]
+    pub fallback: Vec<FallbackTarget>,
+    /// Retries on one provider before failing over (default 2).
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    /// Seconds to stay on a fallback before retrying the primary (default 300).
+    #[serde(default)]
+    pub failover_cooldown_secs: Option<u64>,
+    /// MCP tool count above which the system prompt lists tools as a compact
+    /// index instead of full entries (default 20).
+    #[serde(default)]
+    pub mcp_compact_threshold: Option<usize>,
+    /// Project directories whose `.mash/settings*.json` may set provider endpoints,
+    /// keys and `permissions.allow`. Only read from `~/.mash/settings.json`.
+    #[serde(default)]
+    pub trusted_projects: Vec<String>,
+}
+
+/// Values given on the command line; the highest-precedence layer.
+#[derive(Debug, Clone, Default)]
+pub struct CliOverrides {
+    pub model_provider: Option<String>,
+    pub model: Option<String>,
 }
 
+/// Merged settings together with the origin of every effective value,
+/// keyed by a dotted path such as `model` or `model_providers.deepseek.api_key`.
+#[derive(Debug, Clone)]
+pub struct LayeredSettings {
+    pub settings: Settings,
+    pub origins: BTreeMap<String, String>,
+    /// Non-fatal problems such as unknown keys, prefixed with the file they came from.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct ApiConfig {
+    /// Name of the provider in settings.json this config was resolved from.
+    pub provider: String,
     pub base_url: String,
     pub api_key: String,
     pub model: String,
     pub max_tokens: u32,
 }
 
+/// One configuration source: a parsed JSON object and a label for `config show --origin`.
+struct Layer {
+    origin: String,
+    value: Value,
+}
+
 impl Settings {
-    /// Load from ~/.mash/settings.json. Returns None if file missing or invalid.
-    pub fn load() -> Option<Self> {
-        let path = dirs::home_dir()?.join(".mash").join("settings.json");
-        let content = std::fs::read_to_string(&path).ok()?;
-        serde_json::from_str(&content).ok()
+    /// Load and merge all layers, lowest precedence first:
+    /// built-in defaults < `~/.mash/settings.json` < `<project>/.mash/settings.json`
+    /// < `<project>/.mash/settings.local.json` < env vars < CLI flags.
+    ///
+    /// Scalars are replaced by later layers, `model_providers` are merged field by
+    /// field by `name`, and permission rules are concatenated. The project files
+    /// may only set provider endpoints, keys and `allow` rules for a project listed
+    /// in `trusted_projects`.
+    pub fn load(cli: &CliOverrides) -> Result<LayeredSettings> {
+        // No default `model`: it depends on the provider (see `ApiConfig::for_model`).
+        let mut layers = vec![Layer {
+            origin: "default".to_string(),
+            value: json!({ "max_tokens": DEFAULT_MAX_TOKENS }),
+        }];
+        let mut warnings = Vec::new();
+        let user_path = mash_config_path("settings.json").ok();
+        let project = project_dir();
+        let mut trusted = false;
+        for path in settings_paths() {
+            if !path.exists() {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            // Parse twice: as a Value for merging, and as Settings so type errors
+            // are reported with their line and column in this file.
+            let mut value: Value = parse_json(&path, &content)?;
+            parse_json::<Settings>(&path, &content)?;
+            warnings.extend(
+                unknown_settings_keys(&value)
+                    .into_iter()
+                    .map(|key| format!("{}: unknown key '{key}'", path.display())),
+            );
+            if Some(&path) == user_path.as_ref() {
+                let home = dirs::home_dir();
+                trusted = project
+                    .as_deref()
+                    .is_some_and(|dir| is_trusted_project(&value, dir, home.as_deref()));
+            } else {
+                let dir = project.as_deref().unwrap_or(Path::new("."));
+                warnings.extend(restrict_project_layer(&mut value, trusted).into_iter().map(
+                    |key| {
+                        let hint = if key == "trusted_projects" || key.ends_with(".api_key_command") {
+                            "only read from ~/.mash/settings.json".to_string()
+                        } else {
+                            format!(
+                                "add \"{}\" to trusted_projects in ~/.mash/settings.json to allow it",
+                                dir.display()
+                            )
+                        };
+                        format!("{}: ignored '{key}' ({hint})", path.display())
+                    },
+                ));
+            }
+            layers.push(Layer {
+                origin: path.display().to_string(),
+                value,
+            });
+        }
+        if let Some(dir) = &project
+            && !trusted
+        {
+            let prompt_dir = dir.join(".mash").join("prompt");
+            if prompt_dir.is_dir() {
+                warnings.push(format!(
+                    "{}: ignored prompt overrides (add \"{}\" to trusted_projects in ~/.mash/settings.json to allow them)",
+                    prompt_dir.display(),
+                    dir.display()
+                ));
+            }
+        }
+        layers.push(env_layer());
+        layers.push(cli_layer(cli));
+
+        let mut effective = Map::new();
+        let mut origins = BTreeMap::new();
+        for layer in &layers {
+            merge_layer(&mut effective, &mut origins, layer);
+        }
+
+        // API_KEY / BASE_URL patch whichever provider ended up active.
+        if let Some(layer) = env_provider_layer(&effective) {
+            merge_layer(&mut effective, &mut origins, &layer);
+        }
+
+        let settings: Settings = serde_json::from_value(Value::Object(effective))
+            .context("invalid settings after merging configuration layers")?;
+        Ok(LayeredSettings {
+            settings,
+            origins,
+            warnings,
+        })
+    }
+
+    pub fn active_provider(&self) -> Option<&ModelProvider> {
+        self.model_providers
+            .iter()
+            .find(|p| p.name == self.model_provider)
     }
 }
 
-impl ApiConfig {
-    /// Load from ~/.mash/settings.json using model_provider and model, then fall back to env.
-    pub fn load() -> Self {
-        if let Some(settings) = Settings::load()
-            && let Some(provider) = settings
-                .model_providers
-                .iter()
-                .find(|p| p.name == settings.model_provider)
-        {
-            let base_url = if provider.base_url.is_empty() {
-                DEFAULT_BASE_URL.to_string()
-            } else {
-                provider.base_url.clone()
+/// Parse a JSON config file; errors name the file and carry serde's line/column.
+pub fn parse_json<T: DeserializeOwned>(path: &Path, content: &str) -> Result<T> {
+    serde_json::from_str(content).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// Dotted paths of keys in one settings file that mash does not recognise.
+fn unknown_settings_keys(value: &Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let Some(obj) = value.as_object() else {
+        return unknown;
+    };
+    for (key, value) in obj {
+        if !SETTINGS_KEYS.contains(&key.as_str()) {
+            unknown.push(key.clone());
+        }
+        match key.as_str() {
+            "model_providers" => {
+                for (i, provider) in value.as_array().into_iter().flatten().enumerate() {
+                    for field in provider.as_object().into_iter().flat_map(|o| o.keys()) {
+                        if !PROVIDER_KEYS.contains(&field.as_str()) {
+                            unknown.push(format!("model_providers[{i}].{field}"));
+                        }
+                    }
+                }
+            }
+            "permissions" => {
+                for field in value.as_object().into_iter().flat_map(|o| o.keys()) {
+                    if !PERMISSION_KEYS.contains(&field.as_str()) {
+                        unknown.push(format!("permissions.{field}"));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    unknown
+}
+
+/// Settings files in merge order; duplicates (e.g. running from `~`) are dropped.
+pub fn settings_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Ok(user) = mash_config_path("settings.json") {
+        paths.push(user);
+    }
+    if let Some(project) = project_dir() {
+        let dir = project.join(".mash");
+        for name in ["settings.json", "settings.local.json"] {
+            let path = dir.join(name);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+/// Whether the user settings list `project` in `trusted_projects`. Entries may
+/// start with `~/` or be relative to the home directory; both sides are
+/// canonicalized, so a symlinked checkout matches its target.
+fn is_trusted_project(user: &Value, project: &Path, home: Option<&Path>) -> bool {
+    let canonical =
+        |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let project = canonical(project);
+    user["trusted_projects"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.as_str())
+        .filter_map(|entry| expand_home(entry, home))
+        .any(|entry| canonical(&entry) == project)
+}
+
+/// `~`, `~/x` and relative paths are taken from the home directory.
+fn expand_home(entry: &str, home: Option<&Path>) -> Option<PathBuf> {
+    if Path::new(entry).is_absolute() {
+        return Some(PathBuf::from(entry));
+    }
+    let rest = entry.strip_prefix("~/").unwrap_or(entry);
+    Some(home?.join(rest.trim_start_matches('~')))
+}
+
+/// Whether the current project is listed in the user's `trusted_projects`.
+/// Used for what settings layers cannot express, such as prompt overrides.
+pub fn project_is_trusted() -> bool {
+    let Some(project) = project_dir() else {
+        return false;
+    };
+    let user = mash_config_path("settings.json")
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok());
+    user.is_some_and(|user| is_trusted_project(&user, &project, dirs::home_dir().as_deref()))
+}
+
+/// Drop what a project's settings files may not set, returning the dotted keys
+/// removed. A checked-in file could otherwise point a provider at its own server
+/// (which then receives the user's key) or lift the user's deny rules, so those
+/// keys need the project to be trusted. `trusted_projects` and `api_key_command`
+/// (a shell command run on every start) are never taken from a project.
+fn restrict_project_layer(value: &mut Value, trusted: bool) -> Vec<String> {
+    let mut removed = Vec::new();
+    let Some(obj) = value.as_object_mut() else {
+        return removed;
+    };
+    if obj.remove("trusted_projects").is_some() {
+        removed.push("trusted_projects".to_string());
+    }
+    let fields: &[&str] = if trusted {
+        &["api_key_command"]
+    } else {
+        &["base_url", "api_key", "api_key_command"]
+    };
+    let providers = obj
+        .get_mut("model_providers")
+        .and_then(|v| v.as_array_mut());
+    for (i, provider) in providers.into_iter().flatten().enumerate() {
+        let Some(provider) = provider.as_object_mut() else {
+            continue;
+        };
+        for field in fields {
+            if provider.remove(*field).is_some() {
+                removed.push(format!("model_providers[{i}].{field}"));
+            }
+        }
+    }
+    if trusted {
+        return removed;
+    }
+    let perms = obj.get_mut("permissions").and_then(|v| v.as_object_mut());
+    if let Some(perms) = perms
+        && perms.remove("allow").is_some()
+    {
+        removed.push("permissions.allow".to_string());
+    }
+    removed
+}
+
+fn env_layer() -> Layer {
+    let mut value = Map::new();
+    if let Ok(provider) = std::env::var("MASH_MODEL_PROVIDER") {
+        value.insert("model_provider".to_string(), json!(provider));
+    }
+    if let Ok(model) = std::env::var("MODEL") {
+        value.insert("model".to_string(), json!(model));
+    }
+    if let Some(max_tokens) = std::env::var("MAX_TOKENS")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+    {
+        value.insert("max_tokens".to_string(), json!(max_tokens));
+    }
+    Layer {
+        origin: "env".to_string(),
+        value: Value::Object(value),
+    }
+}
+
+fn cli_layer(cli: &CliOverrides) -> Layer {
+    let mut value = Map::new();
+    if let Some(provider) = &cli.model_provider {
+        value.insert("model_provider".to_string(), json!(provider));
+    }
+    if let Some(model) = &cli.model {
+        value.insert("model".to_string(), json!(model));
+    }
+    Layer {
+        origin: "cli".to_string(),
+        value: Value::Object(value),
+    }
+}
+
+/// Name of the provider defined by `API_KEY` / `BASE_URL` alone.
+const ENV_PROVIDER: &str = "default";
+
+/// `API_KEY` / `BASE_URL` apply to the active provider. Without a configured
+/// provider they define one named `default`, matching the old env-only setup.
+fn env_provider_layer(effective: &Map<String, Value>) -> Option<Layer> {
+    let api_key = std::env::var("API_KEY").ok();
+    let base_url = std::env::var("BASE_URL").ok();
+    if api_key.is_none() && base_url.is_none() {
+        return None;
+    }
+
+    let mut value = Map::new();
+    let name = match effective.get("model_provider").and_then(|v| v.as_str()) {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => {
+            value.insert("model_provider".to_string(), json!(ENV_PROVIDER));
+            ENV_PROVIDER.to_string()
+        }
+    };
+    let mut provider = Map::new();
+    provider.insert("name".to_string(), json!(name));
+    if let Some(key) = api_key {
+        provider.insert("api_key".to_string(), json!(key));
+    }
+    if let Some(url) = base_url {
+        provider.insert("base_url".to_string(), json!(url));
+    }
+    value.insert("model_providers".to_string(), json!([provider]));
+    Some(Layer {
+        origin: "env".to_string(),
+        value: Value::Object(value),
+    })
+}
+
+fn merge_layer(
+    effective: &mut Map<String, Value>,
+    origins: &mut BTreeMap<String, String>,
+    layer: &Layer,
+) {
+    let Some(obj) = layer.value.as_object() else {
+        return;
+    };
+    for (key, value) in obj {
+        match (key.as_str(), value) {
+            ("model_providers", Value::Array(providers)) => {
+                merge_providers(effective, origins, providers, &layer.origin)
+            }
+            ("permissions", Value::Object(rules)) => {
+                merge_permissions(effective, origins, rules, &layer.origin)
+            }
+            _ => {
+                effective.insert(key.clone(), value.clone());
+                origins.insert(key.clone(), layer.origin.clone());
+            }
+        }
+    }
+}
+
+fn merge_providers(
+    effective: &mut Map<String, Value>,
+    origins: &mut BTreeMap<String, String>,
+    providers: &[Value],
+    origin: &str,
+) {
+    let list = effective
+        .entry("model_providers")
+        .or_insert_with(|| json!([]));
+    let Some(list) = list.as_array_mut() else {
+        return;
+    };
+    for provider in providers {
+        let Some(fields) = provider.as_object() else {
+            continue;
+        };
+        let Some(name) = fields.get("name").and_then(|n| n.as_str()) else {
+            continue;
+        };
+        let idx = match list.iter().position(|p| p["name"] == name) {
+            Some(idx) => idx,
+            None => {
+                list.push(json!({ "name": name }));
+                list.len() - 1
+            }
+        };
+        let Some(target) = list[idx].as_object_mut() else {
+            continue;
+        };
+        for (field, value) in fields {
+            if field == "name" {
+                continue;
+            }
+            target.insert(field.clone(), value.clone());
+            origins.insert(
+                format!("model_providers.{name}.{field}"),
+                origin.to_string(),
+            );
+        }
+    }
+}
+
+fn merge_permissions(
+    effective: &mut Map<String, Value>,
+    origins: &mut BTreeMap<String, String>,
+    rules: &Map<String, Value>,
+    origin: &str,
+) {
+    let perms = effective.entry("permissions").or_insert_with(|| json!({}));
+    let Some(perms) = perms.as_object_mut() else {
+        return;
+    };
+    for kind in ["allow", "deny"] {
+        let Some(patterns) = rules.get(kind).and_then(|v| v.as_array()) else {
+            continue;
+        };
+        let list = perms.entry(kind).or_insert_with(|| json!([]));
+        let Some(list) = list.as_array_mut() else {
+            continue;
+        };
+        for pattern in patterns {
+            let Some(p) = pattern.as_str() else {
+                continue;
             };
-            if !provider.api_key.is_empty() {
-                return Self {
-                    base_url,
-                    api_key: provider.api_key.clone(),
-                    model: if settings.model.is_empty() {
-                        "deepseek-chat".to_string()
-                    } else {
-                        settings.model
-                    },
-                    max_tokens: DEFAULT_MAX_TOKENS,
-                };
+            if !list.contains(pattern) {
+                list.push(pattern.clone());
+                origins.insert(format!("permissions.{kind}.{p}"), origin.to_string());
             }
         }
+    }
+}
 
-        Self::from_env()
+impl ModelProvider {
+    /// Resolve the API key: interpolate `api_key`, or run `api_key_command` if it is empty.
+    /// `Settings::load` only keeps an `api_key_command` from the user file, env or CLI.
+    pub fn resolve_api_key(&self) -> Result<String> {
+        let key = interpolate_env(&self.api_key)
+            .with_context(|| format!("in api_key of provider '{}'", self.name))?;
+        if !key.is_empty() {
+            return Ok(key);
+        }
+        let Some(command) = &self.api_key_command else {
+            return Ok(key);
+        };
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .with_context(|| {
+                format!("failed to run api_key_command of provider '{}'", self.name)
+            })?;
+        if !output.status.success() {
+            bail!(
+                "api_key_command of provider '{}' failed ({}): {}",
+                self.name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
     }
+}
 
-    pub fn from_env() -> Self {
-        let api_key = std::env::var("API_KEY").unwrap_or_else(|_| {
-            eprintln!("Error: API_KEY not set");
-            std::process::exit(1);
-        });
+/// Expand `${VAR}` and `${VAR:-default}` references. A referenced variable that is
+/// unset and has no default is an error, so a missing secret is not sent as "".
+pub fn interpolate_env(input: &str) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            bail!("unterminated '${{' in \"{input}\"");
+        };
+        let expr = &after[..end];
+        let (name, default) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expr, None),
+        };
+        // Like the shell, `:-` also substitutes the default for an empty value.
+        match (std::env::var(name), default) {
+            (Ok(value), None) => out.push_str(&value),
+            (Ok(value), Some(_)) if !value.is_empty() => out.push_str(&value),
+            (_, Some(default)) => out.push_str(default),
+            (Err(_), None) => bail!("environment variable '{name}' is not set"),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
 
-        let base_url = std::env::var("BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+impl Permissions {
+    /// Returns the deny rule that blocks `command`, if any.
+    pub fn denied_by(&self, command: &str) -> Option<&str> {
+        if self.allow.iter().any(|p| glob_match(p, command)) {
+            return None;
+        }
+        self.deny
+            .iter()
+            .find(|p| glob_match(p, command))
+            .map(String::as_str)
+    }
+}
 
-        let model =
-            std::env::var("MODEL").unwrap_or_else(|_| "claude-sonnet-4-20250514".to_string());
+/// Minimal glob matching: `*` matches any run of characters, everything else is literal.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
 
-        let max_tokens = std::env::var("MAX_TOKENS")
-            .ok()
-            .and_then(|value| value.parse::<u32>().ok())
-            .unwrap_or(DEFAULT_MAX_TOKENS);
+impl ApiConfig {
+    /// Resolve the active provider and model from layered settings.
+    pub fn load(cli: &CliOverrides) -> Result<Self> {
+        let layered = Settings::load(cli)?;
+        Self::from_settings(&layered.settings)
+    }
 
-        Self {
+    pub fn from_settings(settings: &Settings) -> Result<Self> {
+        Self::for_model(settings, &settings.model_provider, &settings.model)
+    }
+
+    /// Resolve a specific provider/model pair from settings.
+    pub fn for_model(settings: &Settings, provider_name: &str, model: &str) -> Result<Self> {
+        let Some(provider) = settings
+            .model_providers
+            .iter()
+            .find(|p| p.name == provider_name)
+        else {
+            if provider_name.is_empty() {
+                bail!(
+                    "no model provider configured: set model_provider and model_providers in ~/.mash/settings.json, or set API_KEY"
+                );
+            }
+            bail!("model provider '{provider_name}' not found in model_providers");
+        };
+        let api_key = provider.resolve_api_key()?;
+        if api_key.is_empty() {
+            bail!(
+                "provider '{}' has no api_key: set api_key or api_key_command in settings.json, or API_KEY",
+                provider.name
+            );
+        }
+        let base_url = if provider.base_url.is_empty() {
+            DEFAULT_BASE_URL.to_string()
+        } else {
+            provider.base_url.clone()
+        };
+        let model = match model {
+            "" if provider.name == ENV_PROVIDER || base_url == DEFAULT_BASE_URL => DEFAULT_MODEL,
+            "" => DEFAULT_PROVIDER_MODEL,
+            model => model,
+        };
+        Ok(Self {
+            provider: provider.name.clone(),
             base_url,
             api_key,
-            model,
-            max_tokens,
+            model: model.to_string(),
+            max_tokens: settings.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        })
+    }
+
+    /// `provider/model`, as shown in the status line and `/model` menu.
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.provider, self.model)
+    }
+}
+
+impl Settings {
+    /// Every `(provider, model)` pair offered by `/model`: each provider's `models`
+    /// list, plus the configured model for the active provider.
+    pub fn available_models(&self) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+        for provider in &self.model_providers {
+            for model in &provider.models {
+                out.push((provider.name.clone(), model.clone()));
+            }
+            if provider.name == self.model_provider
+                && !self.model.is_empty()
+                && !provider.models.contains(&self.model)
+            {
+                out.push((provider.name.clone(), self.model.clone()));
+            }
+        }
+        out
+    }
+
+    /// Retry/failover tuning, with unset values left at their defaults.
+    pub fn failover_policy(&self) -> FailoverPolicy {
+        let mut policy = FailoverPolicy::default();
+        if let Some(retries) = self.max_retries {
+            policy.max_retries = retries;
+        }
+        if let Some(secs) = self.failover_cooldown_secs {
+            policy.cooldown = Duration::from_secs(secs);
+        }
+        policy
+    }
+
+    pub fn mcp_compact_threshold(&self) -> usize {
+        self.mcp_compact_threshold
+            .unwrap_or(DEFAULT_MCP_COMPACT_THRESHOLD)
+    }
+
+    /// Resolve the `fallback` chain. Entries that cannot be resolved (unknown
+    /// provider, missing key) are skipped and reported as warnings.
+    pub fn fallback_configs(&self) -> (Vec<ApiConfig>, Vec<String>) {
+        let mut configs = Vec::new();
+        let mut warnings = Vec::new();
+        for target in &self.fallback {
+            let model = if target.model.is_empty() {
+                &self.model
+            } else {
+                &target.model
+            };
+            match ApiConfig::for_model(self, &target.provider, model) {
+                Ok(config) => configs.push(config),
+                Err(e) => {
+                    warnings.push(format!("fallback {}/{model} skipped: {e}", target.provider))
+                }
+            }
         }
+        (configs, warnings)
     }
+
+    /// Resolve a `/model` argument: `provider/model` for a configured provider
+    /// (any model name), a model listed by some provider or the current model, or
+    /// a provider name (its first model). Anything else is most likely a typo,
+    /// so it is rejected rather than sent to the API or saved.
+    pub fn resolve_model_choice(
+        &self,
+        choice: &str,
+        current: &ApiConfig,
+    ) -> Result<(String, String)> {
+        if let Some((provider, model)) = choice.split_once('/')
+            && !model.is_empty()
+            && self.model_providers.iter().any(|p| p.name == provider)
+        {
+            return Ok((provider.to_string(), model.to_string()));
+        }
+        if let Some((provider, model)) = self
+            .available_models()
+            .into_iter()
+            .find(|(_, model)| model == choice)
+        {
+            return Ok((provider, model));
+        }
+        if choice == current.model {
+            return Ok((current.provider.clone(), current.model.clone()));
+        }
+        if let Some(provider) = self.model_providers.iter().find(|p| p.name == choice) {
+            let model = provider
+                .models
+                .first()
+                .cloned()
+                .unwrap_or_else(|| current.model.clone());
+            return Ok((provider.name.clone(), model));
+        }
+        bail!(
+            "unknown model '{choice}': no provider lists it (use <provider>/<model> for an unlisted model)"
+        )
+    }
+}
+
+/// Persist a model choice to `~/.mash/settings.json`, keeping all other keys.
+pub fn save_model_choice(provider: &str, model: &str) -> Result<PathBuf> {
+    let path = mash_config_path("settings.json")?;
+    write_model_choice(&path, provider, model)?;
+    Ok(path)
+}
+
+fn write_model_choice(path: &Path, provider: &str, model: &str) -> Result<()> {
+    let mut value: Value = if path.exists() {
+        parse_json(path, &std::fs::read_to_string(path)?)?
+    } else {
+        json!({})
+    };
+    let Some(obj) = value.as_object_mut() else {
+        bail!("{}: top-level value is not an object", path.display());
+    };
+    obj.insert("model_provider".to_string(), json!(provider));
+    obj.insert("model".to_string(), json!(model));
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&value)? + "\n")?;
+    Ok(())
 }
 
 pub fn mash_config_path(filename: &str) -> Result<PathBuf> {
@@ -104,3 +812,296 @@ pub fn mash_config_path(filename: &str) -> Result<PathBuf> {
         dirs::home_dir().ok_or_else(|| anyhow::anyhow!("could not determine home directory"))?;
     Ok(home.join(".mash").join(filename))
 }
+
+/// Walk from `start` upwards until a directory containing `.git` is found.
+/// Returns None if `start` is not inside a git repository.
+pub fn find_git_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// The project directory: the git root containing the cwd, or the cwd itself.
+pub fn project_dir() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    Some(find_git_root(&cwd).unwrap_or(cwd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(layers: &[(&str, Value)]) -> (Map<String, Value>, BTreeMap<String, String>) {
+        let mut effective = Map::new();
+        let mut origins = BTreeMap::new();
+        for (origin, value) in layers {
+            let layer = Layer {
+                origin: origin.to_string(),
+                value: value.clone(),
+            };
+            merge_layer(&mut effective, &mut origins, &layer);
+        }
+        (effective, origins)
+    }
+
+    #[test]
+    fn glob_star_positions() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "main.rsx"));
+        assert!(glob_match("rm -rf *", "rm -rf /"));
+        assert!(!glob_match("rm -rf *", "rm -r /"));
+        assert!(glob_match("git * --force", "git push origin --force"));
+        assert!(!glob_match("git * --force", "git push origin"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(!glob_match("a*b*c", "acb"));
+    }
+
+    #[test]
+    fn glob_empty_pattern_matches_only_empty_text() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "ls"));
+        assert!(glob_match("ls", "ls"));
+        assert!(!glob_match("ls", "ls -la"));
+    }
+
+    #[test]
+    fn later_layers_win_and_record_their_origin() {
+        let (effective, origins) = merged(&[
+            ("default", json!({ "max_tokens": 100 })),
+            ("user", json!({ "model": "a", "max_tokens": 200 })),
+            ("project", json!({ "model": "b" })),
+            ("cli", json!({ "model": "c" })),
+        ]);
+        assert_eq!(effective["model"], "c");
+        assert_eq!(effective["max_tokens"], 200);
+        assert_eq!(origins["model"], "cli");
+        assert_eq!(origins["max_tokens"], "user");
+    }
+
+    #[test]
+    fn providers_merge_field_by_field() {
+        let (effective, origins) = merged(&[
+            (
+                "user",
+                json!({ "model_providers": [{ "name": "ds", "base_url": "u", "api_key": "k" }] }),
+            ),
+            (
+                "local",
+                json!({ "model_providers": [{ "name": "ds", "models": ["m"] }, { "name": "x" }] }),
+            ),
+            (
+                "env",
+                json!({ "model_providers": [{ "name": "ds", "api_key": "e" }] }),
+            ),
+        ]);
+        let providers = effective["model_providers"].as_array().unwrap();
+        assert_eq!(providers.len(), 2);
+        assert_eq!(providers[0]["base_url"], "u");
+        assert_eq!(providers[0]["api_key"], "e");
+        assert_eq!(providers[0]["models"], json!(["m"]));
+        assert_eq!(origins["model_providers.ds.base_url"], "user");
+        assert_eq!(origins["model_providers.ds.api_key"], "env");
+        assert_eq!(origins["model_providers.ds.models"], "local");
+    }
+
+    #[test]
+    fn permissions_accumulate_without_duplicates() {
+        let (effective, origins) = merged(&[
+            ("user", json!({ "permissions": { "deny": ["rm *"] } })),
+            (
+                "project",
+                json!({ "permissions": { "deny": ["rm *", "curl *"] } }),
+            ),
+        ]);
+        assert_eq!(effective["permissions"]["deny"], json!(["rm *", "curl *"]));
+        assert_eq!(origins["permissions.deny.rm *"], "user");
+        assert_eq!(origins["permissions.deny.curl *"], "project");
+    }
+
+    #[test]
+    fn untrusted_project_cannot_redirect_keys_or_allow_commands() {
+        let mut value = json!({
+            "model": "m",
+            "model_providers": [{ "name": "ds", "base_url": "https://evil", "api_key": "k", "models": ["m"] }],
+            "permissions": { "allow": ["*"], "deny": ["curl *"] },
+            "trusted_projects": ["/"],
+        });
+        let removed = restrict_project_layer(&mut value, false);
+        assert_eq!(
+            removed,
+            [
+                "trusted_projects",
+                "model_providers[0].base_url",
+                "model_providers[0].api_key",
+                "permissions.allow",
+            ]
+        );
+        assert_eq!(value["model"], "m");
+        assert_eq!(value["model_providers"][0]["models"], json!(["m"]));
+        assert_eq!(value["permissions"]["deny"], json!(["curl *"]));
+    }
+
+    #[test]
+    fn trusted_project_keeps_everything_but_the_trust_list_and_commands() {
+        let mut value = json!({
+            "model_providers": [{ "name": "ds", "base_url": "https://team", "api_key_command": "cat key" }],
+            "permissions": { "allow": ["cargo *"] },
+            "trusted_projects": ["/"],
+        });
+        assert_eq!(
+            restrict_project_layer(&mut value, true),
+            ["trusted_projects", "model_providers[0].api_key_command"]
+        );
+        assert_eq!(value["model_providers"][0]["base_url"], "https://team");
+        assert_eq!(value["permissions"]["allow"], json!(["cargo *"]));
+        assert!(is_trusted_project(
+            &json!({ "trusted_projects": ["/work/repo"] }),
+            Path::new("/work/repo"),
+            None
+        ));
+        assert!(!is_trusted_project(
+            &json!({}),
+            Path::new("/work/repo"),
+            None
+        ));
+    }
+
+    #[test]
+    fn trusted_projects_expand_home_and_follow_symlinks() {
+        let home = tempfile::tempdir().unwrap();
+        let home = std::fs::canonicalize(home.path()).unwrap();
+        let repo = home.join("src").join("foo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let link = home.join("foo-link");
+        std::os::unix::fs::symlink(&repo, &link).unwrap();
+
+        let trusts = |entry: &str, project: &Path| {
+            is_trusted_project(
+                &json!({ "trusted_projects": [entry] }),
+                project,
+                Some(&home),
+            )
+        };
+        assert!(trusts("~/src/foo", &repo));
+        assert!(trusts("src/foo", &repo));
+        assert!(trusts(&repo.display().to_string(), &link));
+        assert!(trusts("~/foo-link", &repo));
+        assert!(trusts("~/src/foo/", &repo));
+        assert!(!trusts("~/src", &repo));
+        assert!(!trusts("~/src/foo", &home.join("src").join("bar")));
+    }
+
+    fn model_settings() -> Settings {
+        serde_json::from_value(json!({
+            "model_provider": "ds",
+            "model": "deepseek-chat",
+            "model_providers": [
+                { "name": "ds", "api_key": "k", "models": ["deepseek-reasoner"] },
+                { "name": "claude", "api_key": "k", "models": ["claude-sonnet-4", "claude-opus-4"] },
+                { "name": "empty", "api_key": "k" },
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn current_model() -> ApiConfig {
+        ApiConfig {
+            provider: "ds".to_string(),
+            base_url: String::new(),
+            api_key: "k".to_string(),
+            model: "deepseek-chat".to_string(),
+            max_tokens: 1,
+        }
+    }
+
+    #[test]
+    fn available_models_add_the_configured_model() {
+        let models = model_settings().available_models();
+        let pair = |p: &str, m: &str| (p.to_string(), m.to_string());
+        assert_eq!(
+            models,
+            [
+                pair("ds", "deepseek-reasoner"),
+                pair("ds", "deepseek-chat"),
+                pair("claude", "claude-sonnet-4"),
+                pair("claude", "claude-opus-4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn model_choice_resolves_listed_models_and_providers() {
+        let settings = model_settings();
+        let current = current_model();
+        let resolve = |choice: &str| settings.resolve_model_choice(choice, &current).unwrap();
+        let pair = |p: &str, m: &str| (p.to_string(), m.to_string());
+        assert_eq!(resolve("claude-opus-4"), pair("claude", "claude-opus-4"));
+        assert_eq!(resolve("deepseek-chat"), pair("ds", "deepseek-chat"));
+        assert_eq!(resolve("claude"), pair("claude", "claude-sonnet-4"));
+        assert_eq!(resolve("empty"), pair("empty", "deepseek-chat"));
+        // An explicit provider accepts a model it does not list.
+        assert_eq!(resolve("claude/claude-next"), pair("claude", "claude-next"));
+    }
+
+    #[test]
+    fn model_choice_rejects_unknown_names() {
+        let settings = model_settings();
+        let current = current_model();
+        for choice in ["claud-sonet", "nosuch/claude-opus-4", "claude/"] {
+            assert!(
+                settings.resolve_model_choice(choice, &current).is_err(),
+                "{choice}"
+            );
+        }
+    }
+
+    #[test]
+    fn saving_a_model_choice_keeps_other_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".mash").join("settings.json");
+        write_model_choice(&path, "ds", "deepseek-chat").unwrap();
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            json!({ "model_provider": "ds", "model": "deepseek-chat" })
+        );
+
+        std::fs::write(&path, r#"{ "max_tokens": 10, "model": "old" }"#).unwrap();
+        write_model_choice(&path, "claude", "claude-opus-4").unwrap();
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            json!({ "max_tokens": 10, "model_provider": "claude", "model": "claude-opus-4" })
+        );
+
+        std::fs::write(&path, "[]").unwrap();
+        assert!(write_model_choice(&path, "ds", "deepseek-chat").is_err());
+    }
+
+    #[test]
+    fn interpolate_braced_vars_and_defaults() {
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(interpolate_env("${PATH}").unwrap(), path);
+        assert_eq!(interpolate_env("a${PATH}b").unwrap(), format!("a{path}b"));
+        assert_eq!(
+            interpolate_env("${MASH_TEST_UNSET_VAR:-fallback}").unwrap(),
+            "fallback"
+        );
+    }
+
+    #[test]
+    fn interpolate_missing_var_is_an_error() {
+        let err = interpolate_env("sk-${MASH_TEST_UNSET_VAR}").unwrap_err();
+        assert!(err.to_string().contains("MASH_TEST_UNSET_VAR"));
+        assert!(interpolate_env("${PATH").is_err());
+    }
+
+    #[test]
+    fn interpolate_leaves_literal_dollars() {
+        assert_eq!(interpolate_env("pa$$word").unwrap(), "pa$$word");
+        assert_eq!(interpolate_env("$PATH").unwrap(), "$PATH");
+        assert_eq!(interpolate_env("cost: 5$").unwrap(), "cost: 5$");
+    }
+}
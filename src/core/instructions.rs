@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::config::{find_git_root, project_dir};
+
+/// File names recognised as project instruction files, in lookup order per directory.
+pub const INSTRUCTION_FILENAMES: [&str; 2] = ["MASH.md", "AGENTS.md"];
+
+/// An instruction file discovered on disk (`MASH.md` / `AGENTS.md`).
+#[derive(Debug, Clone)]
+pub struct InstructionFile {
+    /// Absolute path to the file.
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// Discover instruction files, most general first:
+/// `~/.mash/` first, then every directory from the git root (or cwd alone when
+/// outside a repository) down to the cwd.
+pub fn discover_instructions() -> Vec<InstructionFile> {
+    let mut dirs_in_order = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        dirs_in_order.push(home.join(".mash"));
+    }
+
+    if let Ok(cwd) = std::env::current_dir() {
+        let mut chain: Vec<PathBuf> = match find_git_root(&cwd) {
+            Some(root) => cwd
+                .ancestors()
+                .take_while(|dir| dir.starts_with(&root))
+                .map(Path::to_path_buf)
+                .collect(),
+            None => vec![cwd],
+        };
+        chain.reverse();
+        dirs_in_order.extend(chain);
+    }
+
+    let mut files = Vec::new();
+    for dir in dirs_in_order {
+        for name in INSTRUCTION_FILENAMES {
+            let path = dir.join(name);
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if content.trim().is_empty() || files.iter().any(|f: &InstructionFile| f.path == path) {
+                continue;
+            }
+            files.push(InstructionFile { path, content });
+        }
+    }
+    files
+}
+
+/// Format instruction files for inclusion in the system prompt.
+pub fn format_instructions_for_prompt(files: &[InstructionFile]) -> String {
+    if files.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("\n\n## Project Instructions\n\n");
+    out.push_str(
+        "以下内容来自用户与项目的说明文件（MASH.md / AGENTS.md），按从通用到具体的顺序排列；\
+         冲突时以靠后（更接近当前目录）的文件为准。\n",
+    );
+    for file in files {
+        out.push_str(&format!(
+            "\n### `{}`\n\n{}\n",
+            file.path.display(),
+            file.content.trim_end()
+        ));
+    }
+    out
+}
+
+/// Prompt sent to the agent by the `/init` slash command.
+pub fn init_prompt() -> String {
+    let target = project_dir()
+        .map(|dir| dir.join("MASH.md").display().to_string())
+        .unwrap_or_else(|| "MASH.md".to_string());
+    format!(
+        "/init\n\n请分析当前仓库，并在 `{target}` 起草一份项目说明文件，供之后的会话加载到 system prompt 中。\n\
+         要求：\n\
+         1. 先用 bash 查看目录结构、README、构建清单（如 Cargo.toml / package.json / Makefile）和 CI 配置；\n\
+         2. 写明构建、测试、lint 的确切命令；\n\
+         3. 总结代码组织、命名与错误处理等约定，以及需要注意的坑；\n\
+         4. 保持简洁（一般不超过 80 行），只写对后续工作有用的内容；\n\
+         5. 若文件已存在，先阅读并在其基础上改进，不要直接覆盖用户内容。"
+    )
+}
@@ -1,15 +1,38 @@
+pub mod config_file;
+pub mod host;
+pub mod logs;
+pub mod schema;
+pub mod serve;
+pub mod transport;
+
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::process::Stdio;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+use std::time::Duration;
 
-use anyhow::{Result, bail};
-use axum::{Json, Router, extract::State, http::StatusCode, routing::post};
+use anyhow::{Context, Result, bail};
+use axum::extract::Request;
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::header::{ACCEPT, AUTHORIZATION, HOST, ORIGIN};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router, routing::get, routing::post};
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
-use tokio::process::{Child, ChildStdin, ChildStdout, Command};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, broadcast, mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+
+use crate::core::api::AnthropicClient;
+use crate::core::config::{glob_match, parse_json};
+use host::{ApprovalKind, ClientHost, SamplingPolicy};
+use logs::StderrLog;
+use transport::{Transport, TransportKind};
 
 // ── Config ──────────────────────────────────────────────────────
 
@@ -19,15 +42,147 @@ pub struct McpConfigFile {
     pub mcp_servers: HashMap<String, McpServerConfig>,
 }
 
+/// Keys accepted for a server entry in mcp.json, used for unknown-key warnings.
+const SERVER_CONFIG_KEYS: [&str; 14] = [
+    "type",
+    "command",
+    "args",
+    "env",
+    "url",
+    "headers",
+    "disabled",
+    "timeout",
+    "startup_timeout",
+    "lazy",
+    "includeTools",
+    "excludeTools",
+    "approveTools",
+    "sampling",
+];
+
+/// Request timeout used when a server does not set `timeout`.
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 60;
+/// Limit on spawning plus the initial handshake when `startup_timeout` is not set.
+pub const DEFAULT_STARTUP_TIMEOUT_SECS: u64 = 30;
+/// Reconnect attempts after a server crashes or fails to start, before giving up.
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct McpServerConfig {
+    /// `stdio` (default), `http` (streamable HTTP) or `sse` (legacy HTTP+SSE).
+    /// Defaults to `http` when only `url` is given.
+    #[serde(rename = "type", default)]
+    pub transport: Option<String>,
+    /// Executable for stdio servers.
+    #[serde(default)]
     pub command: String,
     #[serde(default)]
     pub args: Vec<String>,
+    /// Values may reference environment variables as `${VAR}` or `${VAR:-default}`.
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Endpoint for `http` / `sse` servers; may use `${VAR}` interpolation.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Extra HTTP headers (e.g. `Authorization`); values may use `${VAR}` interpolation.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
     #[serde(default)]
     pub disabled: bool,
+    /// Per-request timeout in seconds (default 60), including `initialize`.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Seconds allowed for starting the server and listing its tools (default 30).
+    #[serde(default)]
+    pub startup_timeout: Option<u64>,
+    /// Start the server on its first use through the HTTP bridge instead of at launch.
+    #[serde(default)]
+    pub lazy: bool,
+    /// Glob patterns (`*` wildcard) of the tools to expose; empty means all.
+    #[serde(rename = "includeTools", default)]
+    pub include_tools: Vec<String>,
+    /// Glob patterns of tools hidden from the model even if included.
+    #[serde(rename = "excludeTools", default)]
+    pub exclude_tools: Vec<String>,
+    /// Glob patterns of tools the TUI user must approve before each call.
+    #[serde(rename = "approveTools", default)]
+    pub approve_tools: Vec<String>,
+    /// Whether the server may ask for completions: `ask` (default), `allow` or `deny`.
+    #[serde(default)]
+    pub sampling: SamplingPolicy,
+}
+
+/// `includeTools` / `excludeTools` of one server.
+#[derive(Debug, Clone, Default)]
+pub struct ToolFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ToolFilter {
+    pub fn allows(&self, tool: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, tool)))
+            && !self.exclude.iter().any(|p| glob_match(p, tool))
+    }
+}
+
+impl McpServerConfig {
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS))
+    }
+
+    pub fn tool_filter(&self) -> ToolFilter {
+        ToolFilter {
+            include: self.include_tools.clone(),
+            exclude: self.exclude_tools.clone(),
+        }
+    }
+
+    pub fn needs_approval(&self, tool: &str) -> bool {
+        self.approve_tools.iter().any(|p| glob_match(p, tool))
+    }
+
+    /// Whether `mash mcp call` may run `tool`, which has no TUI to ask y/n. A tool
+    /// matched by `approveTools` needs `--yes` typed at a terminal, and is refused
+    /// inside a session: there bash, and so this command, is driven by the model.
+    pub fn check_direct_call(
+        &self,
+        tool: &str,
+        confirmed: bool,
+        in_session: bool,
+        interactive: bool,
+    ) -> Result<()> {
+        if !self.needs_approval(tool) {
+            return Ok(());
+        }
+        if in_session {
+            bail!(
+                "tool '{tool}' needs user approval (approveTools); inside a session call it with `mash mcp invoke`, which asks in the TUI"
+            );
+        }
+        if !confirmed || !interactive {
+            bail!(
+                "tool '{tool}' needs user approval (approveTools); pass --yes from an interactive terminal to run it"
+            );
+        }
+        Ok(())
+    }
+
+    pub fn startup_timeout(&self) -> Duration {
+        Duration::from_secs(self.startup_timeout.unwrap_or(DEFAULT_STARTUP_TIMEOUT_SECS))
+    }
+
+    /// One-line description for listings: the command line or `type url`.
+    pub fn describe(&self) -> String {
+        match TransportKind::of(self) {
+            Ok(TransportKind::Stdio) | Err(_) if self.args.is_empty() => self.command.clone(),
+            Ok(TransportKind::Stdio) | Err(_) => {
+                format!("{} {}", self.command, self.args.join(" "))
+            }
+            Ok(TransportKind::Http) => format!("http {}", self.url.as_deref().unwrap_or("")),
+            Ok(TransportKind::Sse) => format!("sse {}", self.url.as_deref().unwrap_or("")),
+        }
+    }
 }
 
 // ── MCP Tool ────────────────────────────────────────────────────
@@ -41,52 +196,250 @@ pub struct McpTool {
     pub input_schema: Value,
 }
 
+// ── Server notifications ────────────────────────────────────────
+
+/// Notifications from a server that the UI may want to react to.
+#[derive(Debug, Clone)]
+pub enum McpEvent {
+    /// `notifications/tools/list_changed`: the tool list should be fetched again.
+    ToolsChanged { server: String },
+    /// `notifications/progress` for a running call, already formatted for display.
+    Progress { server: String, message: String },
+    /// The server's [`ServerHealth`] changed, e.g. it finished starting or reconnected.
+    HealthChanged { server: String },
+}
+
+/// Format `notifications/message` params as a single log line.
+fn format_log_message(params: &Value) -> String {
+    let level = params["level"].as_str().unwrap_or("info");
+    let data = match &params["data"] {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    match params["logger"].as_str() {
+        Some(logger) => format!("[{level}] {logger}: {data}"),
+        None => format!("[{level}] {data}"),
+    }
+}
+
+/// Format `notifications/progress` params, e.g. `indexing 3/10`.
+fn format_progress(params: &Value) -> String {
+    let number = |v: &Value| v.as_f64().map(|n| format!("{n}"));
+    let amount = match (number(&params["progress"]), number(&params["total"])) {
+        (Some(progress), Some(total)) => format!("{progress}/{total}"),
+        (Some(progress), None) => progress,
+        _ => String::new(),
+    };
+    match params["message"].as_str() {
+        Some(message) if amount.is_empty() => message.to_string(),
+        Some(message) => format!("{message} {amount}"),
+        None => amount,
+    }
+}
+
+/// Result of `tools/call`, with every content block rendered as text.
+#[derive(Debug, Clone)]
+pub struct McpToolResult {
+    pub text: String,
+    /// The tool ran but reported failure (`isError`), as opposed to a protocol error.
+    pub is_error: bool,
+    /// `structuredContent`, for callers that want JSON instead of text.
+    pub structured: Option<Value>,
+}
+
+/// Directory for decoded media: created on first use under the system temp dir
+/// with a random name and mode 0700, removed by [`remove_blob_dir`].
+static BLOB_DIR: StdMutex<Option<tempfile::TempDir>> = StdMutex::new(None);
+
+/// Decode base64 media from a tool result into a temp file; returns its path.
+fn save_blob(server: &str, data: &str, mime: &str) -> Result<PathBuf> {
+    use base64::Engine;
+
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data.trim())?;
+    let ext = match mime {
+        "image/jpeg" => "jpg",
+        "image/svg+xml" => "svg",
+        "audio/mpeg" => "mp3",
+        "audio/wav" | "audio/x-wav" => "wav",
+        "application/octet-stream" => "bin",
+        other => other.rsplit('/').next().unwrap_or("bin"),
+    };
+    let mut dir = BLOB_DIR.lock().unwrap();
+    if dir.is_none() {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("mash-mcp-");
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o700));
+        *dir = Some(
+            builder
+                .tempdir()
+                .context("failed to create a directory for MCP media")?,
+        );
+    }
+    let dir = dir.as_ref().expect("created above");
+    let (mut file, path) = tempfile::Builder::new()
+        .prefix(&format!("{}-", server.replace(['/', '\\'], "_")))
+        .suffix(&format!(".{ext}"))
+        .tempfile_in(dir.path())?
+        .keep()?;
+    std::io::Write::write_all(&mut file, &bytes)?;
+    Ok(path)
+}
+
+/// Delete the media saved from tool results in this process; called on exit.
+pub fn remove_blob_dir() {
+    BLOB_DIR.lock().unwrap().take();
+}
+
+// ── MCP Prompt ──────────────────────────────────────────────────
+
+/// A prompt template from `prompts/list`, offered as a slash command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
 // ── MCP Client (single server) ─────────────────────────────────
 
+/// Response slot for one in-flight request, keyed by JSON-RPC id.
+type PendingMap = HashMap<u64, oneshot::Sender<Result<Value>>>;
+
+/// Connection to one MCP server. A background reader task routes each response
+/// to the request waiting for its id, so any number of calls can be in flight.
 pub struct McpClient {
     name: String,
-    _child: Child,
-    stdin: ChildStdin,
-    reader: Lines<BufReader<ChildStdout>>,
-    next_id: u64,
-    tools: Vec<McpTool>,
+    /// Shared with the reader task, which answers the server's own requests.
+    transport: Arc<dyn Transport>,
+    pending: Arc<StdMutex<PendingMap>>,
+    /// Becomes `true` once the server has gone away (process exit, stream end).
+    closed: watch::Receiver<bool>,
+    next_id: AtomicU64,
+    request_timeout: Duration,
+    /// Tools outside the filter are dropped from `tools/list` and refused by `call_tool`.
+    filter: ToolFilter,
+    stderr: Option<StderrLog>,
+    reader: JoinHandle<()>,
+    /// `capabilities` from the server's `initialize` result.
+    capabilities: Value,
+    /// Replaced when the server announces `tools/list_changed`.
+    tools: StdMutex<Vec<McpTool>>,
+    prompts: Vec<McpPrompt>,
 }
 
-impl McpClient {
-    pub async fn connect(name: &str, config: &McpServerConfig) -> Result<Self> {
-        let mut cmd = Command::new(&config.command);
-        cmd.args(&config.args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .kill_on_drop(true);
+impl Drop for McpClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
 
-        for (k, v) in &config.env {
-            cmd.env(k, v);
+impl McpClient {
+    /// Start or open the server and complete the handshake within the
+    /// server's `startup_timeout`.
+    pub async fn connect(
+        name: &str,
+        config: &McpServerConfig,
+        events: broadcast::Sender<McpEvent>,
+        host: ClientHost,
+    ) -> Result<Self> {
+        let limit = config.startup_timeout();
+        match tokio::time::timeout(limit, Self::open(name, config, events, host)).await {
+            Ok(result) => result,
+            Err(_) => bail!("did not start within {}s", limit.as_secs()),
         }
+    }
 
-        let mut child = cmd.spawn()?;
-        let stdin = child.stdin.take().expect("stdin not captured");
-        let stdout = child.stdout.take().expect("stdout not captured");
-        let reader = BufReader::new(stdout).lines();
+    async fn open(
+        name: &str,
+        config: &McpServerConfig,
+        events: broadcast::Sender<McpEvent>,
+        host: ClientHost,
+    ) -> Result<Self> {
+        let connection = transport::connect(name, config).await?;
+        let transport: Arc<dyn Transport> = Arc::from(connection.transport);
+        let pending = Arc::new(StdMutex::new(PendingMap::new()));
+        let (closed_tx, closed) = watch::channel(false);
+        let capabilities = host.capabilities(config.sampling);
+        let reader = tokio::spawn(read_loop(
+            name.to_string(),
+            connection.incoming,
+            Arc::clone(&pending),
+            closed_tx,
+            events,
+            Responder {
+                transport: Arc::clone(&transport),
+                host,
+                sampling: config.sampling,
+            },
+        ));
 
         let mut client = Self {
             name: name.to_string(),
-            _child: child,
-            stdin,
+            transport,
+            pending,
+            closed,
+            next_id: AtomicU64::new(1),
+            request_timeout: config.request_timeout(),
+            filter: config.tool_filter(),
+            stderr: connection.stderr,
             reader,
-            next_id: 1,
-            tools: Vec::new(),
+            capabilities: Value::Null,
+            tools: StdMutex::new(Vec::new()),
+            prompts: Vec::new(),
         };
 
-        client.initialize().await?;
-        client.tools = client.fetch_tools().await?;
+        // A server that dies during the handshake reports its stderr via send_request.
+        client.capabilities = client.initialize(capabilities).await?;
+        client.refresh_tools().await?;
+        // Prompts are optional: a failing `prompts/list` leaves the tools usable.
+        if client.supports("prompts") {
+            match client.list_prompts().await {
+                Ok(prompts) => client.prompts = prompts,
+                Err(e) => {
+                    let _ = logs::append_line(name, &format!("warning: prompts/list failed: {e}"))
+                        .await;
+                }
+            }
+        }
         Ok(client)
     }
 
-    async fn send_request(&mut self, method: &str, params: Value) -> Result<Value> {
-        let id = self.next_id;
-        self.next_id += 1;
+    /// Append the server's last stderr lines to `error`, if it has any.
+    async fn with_stderr(&self, error: anyhow::Error) -> anyhow::Error {
+        let tail = match &self.stderr {
+            Some(log) => log.tail().await,
+            None => Vec::new(),
+        };
+        if tail.is_empty() {
+            return error;
+        }
+        anyhow::anyhow!("{error}\n  stderr:\n    {}", tail.join("\n    "))
+    }
+
+    async fn send_request(&self, method: &str, params: Value) -> Result<Value> {
+        if self.is_closed() {
+            return Err(self
+                .with_stderr(anyhow::anyhow!(
+                    "MCP server '{}' closed connection",
+                    self.name
+                ))
+                .await);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
 
         let request = json!({
             "jsonrpc": "2.0",
@@ -95,120 +448,559 @@ impl McpClient {
             "params": params,
         });
 
-        let mut line = serde_json::to_string(&request)?;
-        line.push('\n');
-        self.stdin.write_all(line.as_bytes()).await?;
-        self.stdin.flush().await?;
+        if let Err(e) = self.transport.send(request).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
 
-        loop {
-            match self.reader.next_line().await? {
-                Some(line) if !line.is_empty() => {
-                    let msg: Value = serde_json::from_str(&line)?;
-                    if msg.get("id").and_then(|v| v.as_u64()) == Some(id) {
-                        if let Some(error) = msg.get("error") {
-                            bail!("MCP error: {}", error);
-                        }
-                        return Ok(msg["result"].clone());
-                    }
-                }
-                Some(_) => continue,
-                None => bail!("MCP server '{}' closed connection", self.name),
+        match tokio::time::timeout(self.request_timeout, rx).await {
+            Ok(Ok(Err(e))) if self.is_closed() => Err(self.with_stderr(e).await),
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => bail!("MCP server '{}' closed connection", self.name),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                let _ = self
+                    .send_notification(
+                        "notifications/cancelled",
+                        json!({ "requestId": id, "reason": "timeout" }),
+                    )
+                    .await;
+                bail!(
+                    "MCP server '{}' did not answer {method} within {}s",
+                    self.name,
+                    self.request_timeout.as_secs()
+                )
             }
         }
     }
 
-    async fn send_notification(&mut self, method: &str, params: Value) -> Result<()> {
+    pub fn is_closed(&self) -> bool {
+        *self.closed.borrow()
+    }
+
+    /// Resolves once the server has gone away.
+    pub async fn wait_closed(&self) {
+        let mut closed = self.closed.clone();
+        let _ = closed.wait_for(|c| *c).await;
+    }
+
+    async fn send_notification(&self, method: &str, params: Value) -> Result<()> {
         let notification = json!({
             "jsonrpc": "2.0",
             "method": method,
             "params": params,
         });
 
-        let mut line = serde_json::to_string(&notification)?;
-        line.push('\n');
-        self.stdin.write_all(line.as_bytes()).await?;
-        self.stdin.flush().await?;
-        Ok(())
+        self.transport.send(notification).await
     }
 
-    async fn initialize(&mut self) -> Result<()> {
-        self.send_request(
-            "initialize",
-            json!({
-                "protocolVersion": "2024-11-05",
-                "capabilities": {},
-                "clientInfo": { "name": "mash", "version": "0.1.0" }
-            }),
-        )
-        .await?;
+    /// Offers `capabilities` (roots, sampling) and returns the server's.
+    async fn initialize(&self, capabilities: Value) -> Result<Value> {
+        let result = self
+            .send_request(
+                "initialize",
+                json!({
+                    "protocolVersion": "2024-11-05",
+                    "capabilities": capabilities,
+                    "clientInfo": { "name": "mash", "version": "0.1.0" }
+                }),
+            )
+            .await?;
 
         self.send_notification("notifications/initialized", json!({}))
             .await?;
-        Ok(())
+        Ok(result["capabilities"].clone())
+    }
+
+    /// Whether the server advertised `capability` (`tools`, `resources`, `prompts`, …).
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.get(capability).is_some()
+    }
+
+    /// Collect every page of a paginated list method (`resources/list`, …).
+    async fn list_all(&self, method: &str, key: &str) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.send_request(method, params).await?;
+            if let Some(page) = result.get(key).and_then(|v| v.as_array()) {
+                items.extend(page.iter().cloned());
+            }
+            match result.get("nextCursor").and_then(|c| c.as_str()) {
+                Some(next) => cursor = Some(next.to_string()),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    pub async fn list_resources(&self) -> Result<Vec<Value>> {
+        self.list_all("resources/list", "resources").await
+    }
+
+    pub async fn list_resource_templates(&self) -> Result<Vec<Value>> {
+        self.list_all("resources/templates/list", "resourceTemplates")
+            .await
+    }
+
+    /// Read a resource; text contents are joined, anything else is returned as JSON.
+    pub async fn read_resource(&self, uri: &str) -> Result<String> {
+        let result = self
+            .send_request("resources/read", json!({ "uri": uri }))
+            .await?;
+        let contents = result["contents"].as_array().cloned().unwrap_or_default();
+        let texts: Vec<&str> = contents
+            .iter()
+            .filter_map(|c| c.get("text").and_then(|t| t.as_str()))
+            .collect();
+        if !texts.is_empty() && texts.len() == contents.len() {
+            return Ok(texts.join("\n"));
+        }
+        Ok(serde_json::to_string_pretty(&result)?)
+    }
+
+    async fn list_prompts(&self) -> Result<Vec<McpPrompt>> {
+        let prompts = self.list_all("prompts/list", "prompts").await?;
+        Ok(serde_json::from_value(Value::Array(prompts))?)
+    }
+
+    /// Prompt templates fetched at connect time.
+    pub fn prompts(&self) -> &[McpPrompt] {
+        &self.prompts
+    }
+
+    /// Expand a prompt template into text: the text of each message, in order.
+    pub async fn get_prompt(
+        &self,
+        prompt: &str,
+        arguments: &HashMap<String, String>,
+    ) -> Result<String> {
+        let result = self
+            .send_request(
+                "prompts/get",
+                json!({ "name": prompt, "arguments": arguments }),
+            )
+            .await?;
+        let texts: Vec<String> = result["messages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|m| match &m["content"] {
+                Value::Object(c) if c.get("type").and_then(|t| t.as_str()) == Some("text") => {
+                    c.get("text").and_then(|t| t.as_str()).map(str::to_string)
+                }
+                Value::Object(c) if c.get("type").and_then(|t| t.as_str()) == Some("resource") => {
+                    c["resource"]["text"].as_str().map(str::to_string)
+                }
+                _ => None,
+            })
+            .collect();
+        if texts.is_empty() {
+            bail!("MCP prompt '{prompt}' on '{}' returned no text", self.name);
+        }
+        Ok(texts.join("\n\n"))
     }
 
-    async fn fetch_tools(&mut self) -> Result<Vec<McpTool>> {
+    async fn fetch_tools(&self) -> Result<Vec<McpTool>> {
         let result = self.send_request("tools/list", json!({})).await?;
         let tools: Vec<McpTool> =
             serde_json::from_value(result.get("tools").cloned().unwrap_or(json!([])))?;
         Ok(tools)
     }
 
-    pub async fn call_tool(&mut self, tool_name: &str, arguments: Value) -> Result<String> {
+    /// Fetch `tools/list` again and replace the cached tools; returns the new count.
+    pub async fn refresh_tools(&self) -> Result<usize> {
+        let mut tools = self.fetch_tools().await?;
+        tools.retain(|t| self.filter.allows(&t.name));
+        let count = tools.len();
+        *self.tools.lock().unwrap() = tools;
+        Ok(count)
+    }
+
+    pub async fn call_tool(&self, tool_name: &str, arguments: Value) -> Result<McpToolResult> {
+        if !self.filter.allows(tool_name) {
+            bail!(
+                "tool '{tool_name}' of MCP server '{}' is excluded by includeTools/excludeTools",
+                self.name
+            );
+        }
+        // Ask for progress notifications; the token only has to be unique per session.
+        let progress_token = self.next_id.fetch_add(1, Ordering::SeqCst);
         let result = self
             .send_request(
                 "tools/call",
-                json!({ "name": tool_name, "arguments": arguments }),
+                json!({
+                    "name": tool_name,
+                    "arguments": arguments,
+                    "_meta": { "progressToken": progress_token },
+                }),
             )
             .await?;
 
-        if let Some(content) = result.get("content").and_then(|c| c.as_array()) {
-            let texts: Vec<&str> = content
-                .iter()
-                .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
-                .collect();
-            if !texts.is_empty() {
-                return Ok(texts.join("\n"));
+        let blocks = result["content"].as_array().cloned().unwrap_or_default();
+        let text = if blocks.is_empty() {
+            serde_json::to_string_pretty(&result)?
+        } else {
+            let mut parts = Vec::with_capacity(blocks.len());
+            for block in &blocks {
+                parts.push(self.render_block(block)?);
             }
-        }
+            parts.join("\n")
+        };
 
-        Ok(serde_json::to_string_pretty(&result)?)
+        Ok(McpToolResult {
+            text,
+            is_error: result["isError"].as_bool().unwrap_or(false),
+            structured: result.get("structuredContent").cloned(),
+        })
+    }
+
+    /// Turn one content block into text: text as-is, media saved to a temp file,
+    /// embedded resources inlined.
+    fn render_block(&self, block: &Value) -> Result<String> {
+        let mime = block["mimeType"]
+            .as_str()
+            .unwrap_or("application/octet-stream");
+        match block["type"].as_str() {
+            Some("text") => Ok(block["text"].as_str().unwrap_or_default().to_string()),
+            Some(kind @ ("image" | "audio")) => {
+                let data = block["data"].as_str().unwrap_or_default();
+                let path = save_blob(&self.name, data, mime)?;
+                Ok(format!("[{kind} {mime} saved to {}]", path.display()))
+            }
+            Some("resource") => {
+                let resource = &block["resource"];
+                let uri = resource["uri"].as_str().unwrap_or("?");
+                if let Some(text) = resource["text"].as_str() {
+                    return Ok(format!("[resource {uri}]\n{text}"));
+                }
+                let mime = resource["mimeType"]
+                    .as_str()
+                    .unwrap_or("application/octet-stream");
+                let data = resource["blob"].as_str().unwrap_or_default();
+                let path = save_blob(&self.name, data, mime)?;
+                Ok(format!(
+                    "[resource {uri} ({mime}) saved to {}]",
+                    path.display()
+                ))
+            }
+            Some("resource_link") => Ok(format!(
+                "[resource link {}{}]",
+                block["uri"].as_str().unwrap_or("?"),
+                block["name"]
+                    .as_str()
+                    .map(|n| format!(" \"{n}\""))
+                    .unwrap_or_default()
+            )),
+            _ => Ok(serde_json::to_string_pretty(block)?),
+        }
     }
 
-    pub fn tools(&self) -> &[McpTool] {
-        &self.tools
+    pub fn tools(&self) -> Vec<McpTool> {
+        self.tools.lock().unwrap().clone()
     }
 
     pub fn tool_count(&self) -> usize {
-        self.tools.len()
+        self.tools.lock().unwrap().len()
+    }
+}
+
+/// Answers requests the server sends to mash (`roots/list`, `sampling/createMessage`).
+#[derive(Clone)]
+struct Responder {
+    transport: Arc<dyn Transport>,
+    host: ClientHost,
+    sampling: SamplingPolicy,
+}
+
+impl Responder {
+    /// Handle one request in the background; sampling may wait for the user.
+    fn spawn(&self, server: &str, id: Value, method: &str, params: Value) {
+        let responder = self.clone();
+        let (server, method) = (server.to_string(), method.to_string());
+        tokio::spawn(async move {
+            let reply = match responder
+                .host
+                .handle_request(&server, responder.sampling, &method, &params)
+                .await
+            {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            };
+            let _ = responder.transport.send(reply).await;
+        });
+    }
+}
+
+/// Dispatch messages from the server until the connection closes, then fail
+/// every request still waiting. Requests and notifications are handled off this
+/// loop, so a slow log write never delays a response.
+async fn read_loop(
+    name: String,
+    mut incoming: mpsc::UnboundedReceiver<Value>,
+    pending: Arc<StdMutex<PendingMap>>,
+    closed: watch::Sender<bool>,
+    events: broadcast::Sender<McpEvent>,
+    responder: Responder,
+) {
+    // One task handles notifications in the order they arrive.
+    let (notify, mut notifications) = mpsc::unbounded_channel::<(String, Value)>();
+    let notify_name = name.clone();
+    tokio::spawn(async move {
+        while let Some((method, params)) = notifications.recv().await {
+            handle_notification(&notify_name, &method, &params, &events).await;
+        }
+    });
+
+    while let Some(msg) = incoming.recv().await {
+        if let Some(method) = msg.get("method").and_then(|m| m.as_str()) {
+            match msg.get("id") {
+                Some(id) => responder.spawn(&name, id.clone(), method, msg["params"].clone()),
+                None => {
+                    let _ = notify.send((method.to_string(), msg["params"].clone()));
+                }
+            }
+            continue;
+        }
+        let Some(id) = msg.get("id").and_then(|v| v.as_u64()) else {
+            continue;
+        };
+        let Some(tx) = pending.lock().unwrap().remove(&id) else {
+            continue;
+        };
+        let result = match msg.get("error") {
+            Some(error) => Err(anyhow::anyhow!("MCP error: {}", error)),
+            None => Ok(msg["result"].clone()),
+        };
+        let _ = tx.send(result);
+    }
+
+    closed.send_replace(true);
+    for (_, tx) in pending.lock().unwrap().drain() {
+        let _ = tx.send(Err(anyhow::anyhow!(
+            "MCP server '{name}' closed connection"
+        )));
+    }
+}
+
+async fn handle_notification(
+    name: &str,
+    method: &str,
+    params: &Value,
+    events: &broadcast::Sender<McpEvent>,
+) {
+    // Sending fails only when nobody is subscribed (e.g. one-shot CLI commands).
+    match method {
+        "notifications/tools/list_changed" => {
+            let _ = events.send(McpEvent::ToolsChanged {
+                server: name.to_string(),
+            });
+        }
+        "notifications/progress" => {
+            let _ = events.send(McpEvent::Progress {
+                server: name.to_string(),
+                message: format_progress(params),
+            });
+        }
+        "notifications/message" => {
+            let _ = logs::append_line(name, &format_log_message(params)).await;
+        }
+        _ => {}
     }
 }
 
 // ── MCP Manager (all servers) ──────────────────────────────────
 
+/// Connection state of a configured server, shown by `mash mcp list` and `/mcp`.
+#[derive(Debug, Clone)]
+pub enum ServerHealth {
+    Disabled,
+    /// `lazy: true` and not used yet.
+    Idle,
+    /// The first connection attempt is under way.
+    Starting,
+    Connected,
+    /// The server went away; `attempt` of [`MAX_RECONNECT_ATTEMPTS`] is under way.
+    Reconnecting {
+        attempt: u32,
+        error: String,
+    },
+    /// Connecting failed and no further attempts will be made.
+    Failed(String),
+}
+
+impl ServerHealth {
+    /// Machine-readable state for the HTTP API.
+    pub fn state(&self) -> &'static str {
+        match self {
+            ServerHealth::Disabled => "disabled",
+            ServerHealth::Idle => "idle",
+            ServerHealth::Starting => "starting",
+            ServerHealth::Connected => "connected",
+            ServerHealth::Reconnecting { .. } => "reconnecting",
+            ServerHealth::Failed(_) => "failed",
+        }
+    }
+
+    /// The last connection error, if the server is not healthy.
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            ServerHealth::Reconnecting { error, .. } | ServerHealth::Failed(error) => Some(error),
+            ServerHealth::Disabled
+            | ServerHealth::Idle
+            | ServerHealth::Starting
+            | ServerHealth::Connected => None,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            ServerHealth::Disabled => "disabled".to_string(),
+            ServerHealth::Idle => "○ idle (starts on first use)".to_string(),
+            ServerHealth::Starting => "… starting".to_string(),
+            ServerHealth::Connected => "✓ connected".to_string(),
+            ServerHealth::Reconnecting { attempt, error } => {
+                format!("↻ reconnecting ({attempt}/{MAX_RECONNECT_ATTEMPTS}): {error}")
+            }
+            ServerHealth::Failed(error) => format!("✗ {error}"),
+        }
+    }
+}
+
+/// Callback for user-facing connection notices (shown in the TUI).
+type Notifier = Arc<dyn Fn(String) + Send + Sync>;
+
+/// A server from the project's mcp.json that the user has not approved yet.
+struct PendingServer {
+    name: String,
+    config: McpServerConfig,
+    /// The raw mcp.json entry the answer is remembered for.
+    entry: Value,
+}
+
 pub struct McpManager {
     configs: HashMap<String, McpServerConfig>,
-    clients: HashMap<String, McpClient>,
+    /// Shared handles, so a call never needs the manager locked while it runs.
+    clients: HashMap<String, Arc<McpClient>>,
+    health: HashMap<String, ServerHealth>,
+    events: broadcast::Sender<McpEvent>,
+    /// Serialises the first start of each lazy server.
+    lazy_starts: HashMap<String, Arc<Mutex<()>>>,
+    /// Set by [`start_servers`]; lazy servers get supervised with it once started.
+    notify: Option<Notifier>,
+    /// Sampling model and approval channel, handed to every client.
+    host: ClientHost,
+    /// Project servers to ask about; [`start_servers`] holds back a user server
+    /// of the same name until the answer is in.
+    pending: Vec<PendingServer>,
+    warnings: Vec<String>,
 }
 
 impl McpManager {
+    /// Load `~/.mash/mcp.json` and then `<project>/.mash/mcp.json`. A project
+    /// entry is only used once the user has approved it (it then replaces a user
+    /// entry of the same name); until then it waits in `pending`.
     pub fn load() -> Result<Self> {
-        let config_path = crate::core::config::mash_config_path("mcp.json")?;
-
-        let configs = if config_path.exists() {
+        let mut warnings = Vec::new();
+        let mut configs = HashMap::new();
+        let mut pending = Vec::new();
+        for (scope, config_path) in config_file::config_paths() {
+            if !config_path.exists() {
+                continue;
+            }
             let content = std::fs::read_to_string(&config_path)?;
-            let file: McpConfigFile = serde_json::from_str(&content)?;
-            file.mcp_servers
-        } else {
-            HashMap::new()
-        };
+            let raw: Value = parse_json(&config_path, &content)?;
+            let file: McpConfigFile = parse_json(&config_path, &content)?;
+            for (name, server) in raw["mcpServers"].as_object().into_iter().flatten() {
+                for key in server.as_object().into_iter().flat_map(|o| o.keys()) {
+                    if !SERVER_CONFIG_KEYS.contains(&key.as_str()) {
+                        warnings.push(format!(
+                            "{}: unknown key 'mcpServers.{name}.{key}'",
+                            config_path.display()
+                        ));
+                    }
+                }
+            }
+            if scope == config_file::McpScope::User {
+                configs.extend(file.mcp_servers);
+                continue;
+            }
+            for (name, config) in file.mcp_servers {
+                let entry = raw["mcpServers"][&name].clone();
+                match config_file::project_server_decision(&name, &entry) {
+                    Some(true) => {
+                        configs.insert(name, config);
+                    }
+                    Some(false) => warnings.push(format!(
+                        "{}: MCP server '{name}' was declined; remove it from ~/.mash/mcp-approvals.json to be asked again",
+                        config_path.display()
+                    )),
+                    None => pending.push(PendingServer {
+                        name,
+                        config,
+                        entry,
+                    }),
+                }
+            }
+        }
+        pending.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let health = configs
+            .iter()
+            .filter_map(|(n, c)| match c {
+                c if c.disabled => Some((n.clone(), ServerHealth::Disabled)),
+                c if c.lazy => Some((n.clone(), ServerHealth::Idle)),
+                _ => None,
+            })
+            .collect();
+        let lazy_starts = configs
+            .iter()
+            .filter(|(_, c)| c.lazy && !c.disabled)
+            .map(|(n, _)| (n.clone(), Arc::new(Mutex::new(()))))
+            .collect();
 
         Ok(Self {
             configs,
             clients: HashMap::new(),
+            health,
+            events: broadcast::channel(64).0,
+            lazy_starts,
+            notify: None,
+            host: ClientHost::default(),
+            pending,
+            warnings,
         })
     }
 
+    /// Names of project servers still waiting for the user's approval.
+    pub fn pending_project_servers(&self) -> Vec<String> {
+        self.pending.iter().map(|p| p.name.clone()).collect()
+    }
+
+    /// Make `config` the configuration of `name`, as `load` would have.
+    fn add_config(&mut self, name: &str, config: McpServerConfig) {
+        if config.disabled {
+            self.set_health(name, ServerHealth::Disabled);
+        } else if config.lazy {
+            self.lazy_starts
+                .insert(name.to_string(), Arc::new(Mutex::new(())));
+            self.set_health(name, ServerHealth::Idle);
+        }
+        self.configs.insert(name.to_string(), config);
+    }
+
+    /// Non-fatal problems found while loading mcp.json (e.g. unknown keys).
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
     pub fn configs(&self) -> &HashMap<String, McpServerConfig> {
         &self.configs
     }
@@ -228,22 +1020,82 @@ impl McpManager {
             bail!("MCP server '{}' is disabled", name);
         }
 
-        let client = McpClient::connect(name, &config).await?;
-        self.clients.insert(name.to_string(), client);
-        Ok(())
+        match McpClient::connect(name, &config, self.events.clone(), self.host.clone()).await {
+            Ok(client) => {
+                self.insert_client(name, client);
+                Ok(())
+            }
+            Err(e) => {
+                self.set_health(name, ServerHealth::Failed(e.to_string()));
+                Err(e)
+            }
+        }
     }
 
-    pub async fn connect_all(&mut self) {
-        let names: Vec<String> = self
-            .configs
+    /// What [`McpClient::connect`] needs from the manager.
+    fn connect_context(&self) -> (broadcast::Sender<McpEvent>, ClientHost) {
+        (self.events.clone(), self.host.clone())
+    }
+
+    fn insert_client(&mut self, name: &str, client: McpClient) {
+        self.clients.insert(name.to_string(), Arc::new(client));
+        self.set_health(name, ServerHealth::Connected);
+    }
+
+    fn set_health(&mut self, name: &str, health: ServerHealth) {
+        self.health.insert(name.to_string(), health);
+        let _ = self.events.send(McpEvent::HealthChanged {
+            server: name.to_string(),
+        });
+    }
+
+    /// Route `approveTools` calls and sampling requests to `approvals` (the TUI's prompt).
+    pub fn set_approvals(&mut self, approvals: mpsc::UnboundedSender<host::ApprovalRequest>) {
+        self.host.approvals = Some(approvals);
+    }
+
+    /// Answer servers' `sampling/createMessage` with the provider `model` is using.
+    /// Applies to servers connected from now on.
+    pub fn set_sampler(&mut self, model: Arc<AnthropicClient>) {
+        self.host.model = Some(model);
+    }
+
+    /// Notifications from every connected server (tool list changes, progress).
+    pub fn subscribe(&self) -> broadcast::Receiver<McpEvent> {
+        self.events.subscribe()
+    }
+
+    /// Health of a configured server; `None` if it was never connected.
+    pub fn health(&self, name: &str) -> Option<&ServerHealth> {
+        self.health.get(name)
+    }
+
+    /// Error for a call to a server that has no live client, naming its state.
+    fn unavailable(&self, name: &str) -> String {
+        match self.health(name) {
+            Some(health) => format!("MCP server '{name}' not connected ({})", health.label()),
+            None => format!("MCP server '{name}' not connected"),
+        }
+    }
+
+    /// Servers still making their first connection attempt.
+    pub fn starting_count(&self) -> usize {
+        self.health
+            .values()
+            .filter(|h| matches!(h, ServerHealth::Starting))
+            .count()
+    }
+
+    /// Lazy servers that have not been started yet.
+    pub fn idle_servers(&self) -> Vec<String> {
+        let mut idle: Vec<String> = self
+            .health
             .iter()
-            .filter(|(_, c)| !c.disabled)
+            .filter(|(_, h)| matches!(h, ServerHealth::Idle))
             .map(|(n, _)| n.clone())
             .collect();
-
-        for name in names {
-            let _ = self.connect(&name).await;
-        }
+        idle.sort();
+        idle
     }
 
     pub fn tool_definitions(&self) -> Vec<Value> {
@@ -260,7 +1112,7 @@ impl McpManager {
         defs
     }
 
-    pub async fn call_tool(&mut self, full_name: &str, arguments: &Value) -> Result<String> {
+    pub async fn call_tool(&self, full_name: &str, arguments: &Value) -> Result<String> {
         let rest = full_name
             .strip_prefix("mcp__")
             .ok_or_else(|| anyhow::anyhow!("Invalid MCP tool name: {}", full_name))?;
@@ -273,25 +1125,497 @@ impl McpManager {
         let tool_name = &rest[sep + 2..];
 
         let client = self
-            .clients
-            .get_mut(server_name)
-            .ok_or_else(|| anyhow::anyhow!("MCP server '{}' not connected", server_name))?;
+            .client_handle(server_name)
+            .ok_or_else(|| anyhow::anyhow!(self.unavailable(server_name)))?;
+
+        let result = client.call_tool(tool_name, arguments.clone()).await?;
+        if result.is_error {
+            bail!("{}", result.text);
+        }
+        Ok(result.text)
+    }
 
-        client.call_tool(tool_name, arguments.clone()).await
+    /// Shared handle to a connected client, usable after the manager lock is released.
+    pub fn client_handle(&self, name: &str) -> Option<Arc<McpClient>> {
+        self.clients.get(name).cloned()
     }
 
     pub fn get_client(&self, name: &str) -> Option<&McpClient> {
-        self.clients.get(name)
+        self.clients.get(name).map(|c| c.as_ref())
+    }
+
+    /// Tools of every connected server, after filtering.
+    pub fn tool_count(&self) -> usize {
+        self.clients.values().map(|c| c.tool_count()).sum()
     }
 
     /// 按 server 遍历所有工具，用于生成 system prompt。
-    pub fn iter_servers_and_tools(&self) -> impl Iterator<Item = (String, &[McpTool])> + '_ {
+    pub fn iter_servers_and_tools(&self) -> impl Iterator<Item = (String, Vec<McpTool>)> + '_ {
         self.clients.iter().map(|(n, c)| (n.clone(), c.tools()))
     }
+
+    /// Prompt templates of every connected server, sorted by server then name.
+    pub fn prompts(&self) -> Vec<(String, McpPrompt)> {
+        let mut prompts: Vec<(String, McpPrompt)> = self
+            .clients
+            .iter()
+            .flat_map(|(n, c)| c.prompts().iter().map(|p| (n.clone(), p.clone())))
+            .collect();
+        prompts.sort_by(|a, b| (&a.0, &a.1.name).cmp(&(&b.0, &b.1.name)));
+        prompts
+    }
+
+    /// Connected servers that advertised `capability`, sorted by name.
+    pub fn servers_supporting(&self, capability: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .clients
+            .iter()
+            .filter(|(_, c)| c.supports(capability))
+            .map(|(n, _)| n.clone())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+// ── Supervision (crash detection + reconnect) ─────────────────────
+
+/// Connect every enabled, non-lazy server concurrently and keep each one
+/// connected. Returns at once; progress shows up as [`McpEvent::HealthChanged`].
+pub async fn start_servers(
+    mcp: Arc<Mutex<McpManager>>,
+    notify: impl Fn(String) + Send + Sync + 'static,
+) {
+    let notify: Notifier = Arc::new(notify);
+    let mut manager = mcp.lock().await;
+    manager.notify = Some(Arc::clone(&notify));
+    let pending = std::mem::take(&mut manager.pending);
+    let mut names: Vec<String> = manager
+        .configs
+        .iter()
+        .filter(|(n, c)| !c.disabled && !c.lazy && !pending.iter().any(|p| &p.name == *n))
+        .map(|(n, _)| n.clone())
+        .collect();
+    names.sort();
+    for name in names {
+        start_server(&mut manager, &name, &mcp, &notify);
+    }
+    let host = manager.host.clone();
+    drop(manager);
+    if !pending.is_empty() && host.approvals.is_some() {
+        tokio::spawn(review_project_servers(pending, host, mcp, notify));
+    }
+}
+
+fn start_server(
+    manager: &mut McpManager,
+    name: &str,
+    mcp: &Arc<Mutex<McpManager>>,
+    notify: &Notifier,
+) {
+    manager.set_health(name, ServerHealth::Starting);
+    tokio::spawn(supervise(
+        name.to_string(),
+        manager.configs[name].clone(),
+        Arc::clone(mcp),
+        Arc::clone(notify),
+    ));
+}
+
+/// Ask the user about each new project server, remember the answer, and start
+/// the approved ones. A declined server's user entry of the same name (if any)
+/// starts instead.
+async fn review_project_servers(
+    pending: Vec<PendingServer>,
+    host: ClientHost,
+    mcp: Arc<Mutex<McpManager>>,
+    notify: Notifier,
+) {
+    for server in pending {
+        let replaces_user = mcp.lock().await.configs.contains_key(&server.name);
+        let kind = ApprovalKind::Server {
+            command: server.config.describe(),
+            sampling: server.config.sampling,
+            replaces_user,
+        };
+        let approved = host.approve(&server.name, kind).await;
+        if let Err(e) = config_file::remember_project_server(&server.name, &server.entry, approved)
+        {
+            notify(format!("⚠ MCP: {}: {e:#}", server.name));
+        }
+        let mut manager = mcp.lock().await;
+        if approved {
+            manager.add_config(&server.name, server.config);
+        }
+        let startable = manager
+            .configs
+            .get(&server.name)
+            .is_some_and(|c| !c.disabled && !c.lazy);
+        if startable {
+            start_server(&mut manager, &server.name, &mcp, &notify);
+        }
+    }
+}
+
+/// The client for `name`, starting it first if it is a lazy server that has
+/// not been used yet. The error is meant for HTTP responses.
+pub async fn client_for(
+    mcp: &Arc<Mutex<McpManager>>,
+    name: &str,
+) -> Result<Arc<McpClient>, String> {
+    let gate = {
+        let manager = mcp.lock().await;
+        if let Some(client) = manager.client_handle(name) {
+            return Ok(client);
+        }
+        match manager.lazy_starts.get(name) {
+            Some(gate)
+                if matches!(
+                    manager.health(name),
+                    Some(ServerHealth::Idle | ServerHealth::Starting)
+                ) =>
+            {
+                Arc::clone(gate)
+            }
+            _ => return Err(manager.unavailable(name)),
+        }
+    };
+
+    // A concurrent first call waits here and then finds the client.
+    let _started = gate.lock().await;
+    let (config, events, host) = {
+        let mut manager = mcp.lock().await;
+        if let Some(client) = manager.client_handle(name) {
+            return Ok(client);
+        }
+        manager.set_health(name, ServerHealth::Starting);
+        let (events, host) = manager.connect_context();
+        (manager.configs[name].clone(), events, host)
+    };
+    let client = match McpClient::connect(name, &config, events, host).await {
+        Ok(client) => client,
+        Err(e) => {
+            // Stay idle so the next call tries again.
+            mcp.lock().await.set_health(name, ServerHealth::Idle);
+            return Err(format!("MCP server '{name}' failed to start: {e}"));
+        }
+    };
+    let count = client.tool_count();
+    let mut manager = mcp.lock().await;
+    manager.insert_client(name, client);
+    let handle = manager.client_handle(name).expect("just inserted");
+    if let Some(notify) = manager.notify.clone() {
+        notify(format!("✓ MCP: {name} 已按需启动（{count} tools）"));
+        tokio::spawn(supervise(name.to_string(), config, Arc::clone(mcp), notify));
+    }
+    Ok(handle)
+}
+
+async fn supervise(
+    name: String,
+    config: McpServerConfig,
+    mcp: Arc<Mutex<McpManager>>,
+    notify: Notifier,
+) {
+    if mcp.lock().await.client_handle(&name).is_none() {
+        let (events, host) = mcp.lock().await.connect_context();
+        match McpClient::connect(&name, &config, events, host).await {
+            Ok(client) => {
+                let count = client.tool_count();
+                mcp.lock().await.insert_client(&name, client);
+                notify(format!("✓ MCP: {name} ({count} tools)"));
+            }
+            Err(e) => {
+                notify(format!("✗ MCP: {name} — {e}"));
+                mcp.lock()
+                    .await
+                    .set_health(&name, ServerHealth::Failed(e.to_string()));
+            }
+        }
+    }
+
+    loop {
+        let client = mcp.lock().await.client_handle(&name);
+        let mut error = match client {
+            Some(client) => {
+                client.wait_closed().await;
+                let mut manager = mcp.lock().await;
+                if manager
+                    .clients
+                    .get(&name)
+                    .is_some_and(|c| Arc::ptr_eq(c, &client))
+                {
+                    manager.clients.remove(&name);
+                }
+                notify(format!("⚠ MCP: {name} 连接已断开，正在重连"));
+                "connection closed".to_string()
+            }
+            None => match mcp.lock().await.health(&name) {
+                Some(ServerHealth::Failed(e)) => e.clone(),
+                _ => return,
+            },
+        };
+
+        let mut reconnected = false;
+        for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+            mcp.lock().await.set_health(
+                &name,
+                ServerHealth::Reconnecting {
+                    attempt,
+                    error: error.clone(),
+                },
+            );
+            tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+            // Connect without holding the manager lock; calls to other servers keep working.
+            let (events, host) = mcp.lock().await.connect_context();
+            match McpClient::connect(&name, &config, events, host).await {
+                Ok(client) => {
+                    let count = client.tool_count();
+                    // HealthChanged lets the UI rebuild the prompt with the new tools.
+                    mcp.lock().await.insert_client(&name, client);
+                    notify(format!("✓ MCP: {name} 已重新连接（{count} tools）"));
+                    reconnected = true;
+                    break;
+                }
+                Err(e) => error = e.to_string(),
+            }
+        }
+
+        if !reconnected {
+            notify(format!(
+                "✗ MCP: {name} 重连 {MAX_RECONNECT_ATTEMPTS} 次失败：{error}"
+            ));
+            mcp.lock()
+                .await
+                .set_health(&name, ServerHealth::Failed(error));
+            return;
+        }
+    }
 }
 
 // ── MCP HTTP API (curl/wget 驱动) ─────────────────────────────────
 
+pub const DEFAULT_MCP_HTTP_PORT: u16 = 31415;
+/// Description characters kept per tool in the compact prompt index.
+const COMPACT_SUMMARY_CHARS: usize = 80;
+
+/// Environment variable carrying the session token into bash subprocesses.
+pub const MCP_TOKEN_ENV: &str = "MASH_MCP_TOKEN";
+/// Environment variable carrying the bridge address (`BridgeAddr`'s display form)
+/// into bash subprocesses, for `mash mcp invoke`.
+pub const MCP_BRIDGE_ENV: &str = "MASH_MCP_BRIDGE";
+/// Auth header as written in the prompt's curl examples; bash expands the variable,
+/// so the token itself never appears in the system prompt.
+const AUTH_HEADER: &str = "-H \"Authorization: Bearer $MASH_MCP_TOKEN\"";
+
+static SESSION_TOKEN: OnceLock<String> = OnceLock::new();
+static SESSION_BRIDGE: OnceLock<BridgeAddr> = OnceLock::new();
+
+/// Generate this session's bridge token (once); later calls return the same one.
+pub fn init_session_token() -> &'static str {
+    SESSION_TOKEN.get_or_init(random_token)
+}
+
+/// The bridge token, if this process runs the bridge.
+pub fn session_token() -> Option<&'static str> {
+    SESSION_TOKEN.get().map(String::as_str)
+}
+
+/// Record where this session's bridge listens, for bash subprocesses.
+pub fn set_session_bridge(addr: BridgeAddr) {
+    let _ = SESSION_BRIDGE.set(addr);
+}
+
+/// The bridge address, if this process runs the bridge.
+pub fn session_bridge() -> Option<&'static BridgeAddr> {
+    SESSION_BRIDGE.get()
+}
+
+/// 32 random hex characters from the OS, falling back to std's randomly seeded hasher.
+fn random_token() -> String {
+    use std::io::Read;
+    let mut bytes = [0u8; 16];
+    let from_os = std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes));
+    if from_os.is_err() {
+        use std::hash::{BuildHasher, Hasher};
+        for chunk in bytes.chunks_mut(8) {
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            hasher.write_u128(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_nanos())
+                    .unwrap_or_default(),
+            );
+            chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+    }
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Port requested with `MCP_HTTP_PORT`; `None` lets [`bind_bridge`] pick one.
+pub fn configured_http_port() -> Result<Option<u16>> {
+    match std::env::var("MCP_HTTP_PORT") {
+        Ok(port) => match port.parse() {
+            Ok(port) => Ok(Some(port)),
+            Err(_) => bail!("MCP_HTTP_PORT must be a port number, got '{port}'"),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// Whether `MCP_HTTP_SOCKET` asks for a unix socket instead of TCP.
+fn socket_requested() -> bool {
+    std::env::var("MCP_HTTP_SOCKET").is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Where the bridge listens, as the system prompt tells the model to reach it.
+#[derive(Debug, Clone)]
+pub enum BridgeAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl BridgeAddr {
+    /// URL prefix for requests; over a unix socket the host part is nominal.
+    pub fn base_url(&self) -> String {
+        match self {
+            BridgeAddr::Tcp(addr) => format!("http://{addr}"),
+            BridgeAddr::Unix(_) => "http://localhost".to_string(),
+        }
+    }
+
+    /// The curl invocation that reaches the bridge.
+    pub fn curl(&self) -> String {
+        match self {
+            BridgeAddr::Tcp(_) => "curl -s".to_string(),
+            BridgeAddr::Unix(path) => format!("curl -s --unix-socket {}", path.display()),
+        }
+    }
+}
+
+impl FromStr for BridgeAddr {
+    type Err = String;
+
+    /// Parses the display form: `127.0.0.1:31415` or `unix:/path/to.sock`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(path) => Ok(BridgeAddr::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(BridgeAddr::Tcp)
+                .map_err(|_| format!("invalid MCP bridge address '{s}'")),
+        }
+    }
+}
+
+impl std::fmt::Display for BridgeAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BridgeAddr::Tcp(addr) => write!(f, "{addr}"),
+            BridgeAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Per-session unix socket: `$XDG_RUNTIME_DIR/mash/mcp-<pid>.sock`, or
+/// `<temp>/mash-<uid>/mcp-<pid>.sock` when there is no runtime dir.
+fn socket_path() -> PathBuf {
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir.join("mash"),
+        None => std::env::temp_dir().join(format!("mash-{}", current_uid())),
+    };
+    dir.join(format!("mcp-{}.sock", std::process::id()))
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    unsafe { libc::geteuid() }
+}
+
+/// Create `dir` with mode 0700, or check that an existing one is a real
+/// directory owned by us that nobody else can enter. In a shared temp dir
+/// another user could otherwise swap the socket and collect the bearer token.
+fn ensure_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    if let Err(e) = std::fs::DirBuilder::new().mode(0o700).create(dir)
+        && e.kind() != std::io::ErrorKind::AlreadyExists
+    {
+        return Err(e).with_context(|| format!("cannot create {}", dir.display()));
+    }
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() {
+        bail!("{} is not a directory", dir.display());
+    }
+    if meta.uid() != current_uid() {
+        bail!("{} is owned by another user", dir.display());
+    }
+    if meta.mode() & 0o077 != 0 {
+        bail!(
+            "{} is accessible to other users (mode {:o}); it must be 0700",
+            dir.display(),
+            meta.mode() & 0o777
+        );
+    }
+    Ok(())
+}
+
+/// The address a session would normally get, without binding it
+/// (for `mash prompt show`; a busy default port makes the real one differ).
+pub fn expected_bridge_addr() -> Result<BridgeAddr> {
+    if socket_requested() {
+        return Ok(BridgeAddr::Unix(socket_path()));
+    }
+    let port = configured_http_port()?.unwrap_or(DEFAULT_MCP_HTTP_PORT);
+    Ok(BridgeAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], port))))
+}
+
+/// A bound bridge socket, ready for [`run_mcp_http_server`].
+pub enum BridgeListener {
+    Tcp(tokio::net::TcpListener),
+    Unix(tokio::net::UnixListener),
+}
+
+/// Bind the bridge before anything refers to its address:
+/// - `MCP_HTTP_SOCKET=1`: a per-session socket under `$XDG_RUNTIME_DIR/mash/`;
+/// - `MCP_HTTP_PORT=<port>`: exactly that port, failing if it is taken;
+/// - otherwise port 31415, or any free port when another session holds it.
+pub async fn bind_bridge() -> Result<(BridgeListener, BridgeAddr)> {
+    if socket_requested() {
+        use std::os::unix::fs::FileTypeExt;
+
+        let path = socket_path();
+        if let Some(dir) = path.parent() {
+            if let Some(base) = dir.parent() {
+                std::fs::create_dir_all(base)?;
+            }
+            ensure_private_dir(dir)
+                .context("refusing to put the MCP bridge socket there (MCP_HTTP_SOCKET)")?;
+        }
+        // A stale socket of an earlier process with our pid; nothing else is removed.
+        if std::fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_socket()) {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = tokio::net::UnixListener::bind(&path)
+            .with_context(|| format!("cannot listen on {}", path.display()))?;
+        return Ok((BridgeListener::Unix(listener), BridgeAddr::Unix(path)));
+    }
+
+    let listener = match configured_http_port()? {
+        Some(port) => tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .with_context(|| {
+                format!("cannot listen on 127.0.0.1:{port} (MCP_HTTP_PORT); unset it to pick a free port")
+            })?,
+        None => match tokio::net::TcpListener::bind(("127.0.0.1", DEFAULT_MCP_HTTP_PORT)).await {
+            Ok(listener) => listener,
+            Err(_) => tokio::net::TcpListener::bind(("127.0.0.1", 0)).await?,
+        },
+    };
+    let addr = listener.local_addr()?;
+    Ok((BridgeListener::Tcp(listener), BridgeAddr::Tcp(addr)))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct McpCallRequest {
     pub server: String,
@@ -300,13 +1624,101 @@ pub struct McpCallRequest {
     pub arguments: Value,
 }
 
-/// 将 MCP 工具列表格式化为 system prompt 片段：工具名、两行描述、params 列表、curl 示例。
-pub fn format_mcp_tools_for_prompt(mcp: &McpManager, base_url: &str) -> String {
+/// `?structured=true` asks `/mcp/call` for JSON, like `Accept: application/json`.
+#[derive(Debug, Deserialize)]
+pub struct CallQuery {
+    #[serde(default)]
+    pub structured: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct McpReadRequest {
+    pub server: String,
+    pub uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct McpPromptRequest {
+    pub server: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub arguments: HashMap<String, String>,
+}
+
+/// `?server=` filter for the list routes; all servers when omitted.
+#[derive(Debug, Deserialize)]
+pub struct ServerQuery {
+    pub server: Option<String>,
+}
+
+/// 将 MCP 工具列表格式化为 system prompt 片段。工具不超过 `compact_threshold`
+/// 个时逐个列出：工具名、两行描述、params 列表、`mash mcp invoke` 调用示例；
+/// 超过时只按 server 分组列出工具名与一句话简介，参数 schema 由模型按需查询。
+pub fn format_mcp_tools_for_prompt(
+    mcp: &McpManager,
+    bridge: &BridgeAddr,
+    compact_threshold: usize,
+) -> String {
+    let base = bridge.base_url();
+    let curl = format!("{} {AUTH_HEADER}", bridge.curl());
+    let invoke = format!("{} mcp invoke", mash_command());
+    let tool_count = mcp.tool_count();
+    let compact = tool_count > compact_threshold;
+    let mut lines = if compact {
+        compact_tool_index(mcp)
+    } else {
+        full_tool_entries(mcp, &invoke)
+    };
+    // Lazy servers have no tool list until something starts them.
+    for server_name in mcp.idle_servers() {
+        lines.push(format!(
+            "- 服务器 **{server_name}**（按需启动）：先 `{curl} '{base}/mcp/tools?server={server_name}'` 启动并列出工具"
+        ));
+    }
+    if lines.is_empty() {
+        return String::new();
+    }
+    let listing = if compact {
+        format!(
+            "共 {tool_count} 个工具，下面只列出名称与简介。调用前先用 `{invoke} --schema <server> <tool>` 查看参数 schema。\n\n"
+        )
+    } else {
+        format!(
+            "可随时 `{curl} {base}/mcp/tools` 重新列出工具，`{invoke} --schema <server> <tool>` 查看完整参数 schema。\n\n"
+        )
+    };
+    let header = format!(
+        "\n\n## MCP 工具（通过 bash 调用 `mash mcp invoke`）\n\
+        需要调用以下工具时，请用 bash 执行 `{invoke} <server> <tool> [参数...]`：\
+        字符串参数写成 `key=value`，数字、布尔、数组、对象写成 `key:=<JSON>`（如 `count:=3`、`opts:='{{\"deep\":true}}'`）；\
+        参数复杂时也可以把整个 JSON 对象从 stdin 传入：`echo '{{...}}' | {invoke} <server> <tool>`。不需要自己拼 curl 和认证头。\n\
+        结果直接打印（JSON 会格式化），需要结构化结果时加 `--structured`。退出码：0 成功；1 工具执行失败（stderr 为原因）；\
+        2 参数不符合 schema（stderr 逐项列出出错字段与期望类型，据此修正后重试）；3 工具被禁用或用户拒绝了这次调用，不要重试；4 server 不可用；5 没有这个 server 或工具（检查名称）。\
+        图片/音频会保存为临时文件并返回路径。\n\
+        {listing}"
+    );
+    format!(
+        "{header}{}{}",
+        lines.join("\n\n"),
+        format_mcp_resources_for_prompt(mcp, &base, &curl)
+    )
+}
+
+/// Marker for tools matched by the server's `approveTools`.
+fn approval_marker(mcp: &McpManager, server: &str, tool: &str) -> &'static str {
+    match mcp.configs.get(server) {
+        Some(c) if c.needs_approval(tool) => "（每次调用需用户确认）",
+        _ => "",
+    }
+}
+
+/// One block per tool: description, params and a ready-made `mash mcp invoke` call.
+fn full_tool_entries(mcp: &McpManager, invoke: &str) -> Vec<String> {
     let mut lines = Vec::new();
-    let base = base_url.trim_end_matches('/');
     for (server_name, tools) in mcp.iter_servers_and_tools() {
         for tool in tools {
             let full_name = format!("mcp__{}__{}", server_name, tool.name);
+            let approval = approval_marker(mcp, &server_name, &tool.name);
             let desc_short: String = tool
                 .description
                 .as_deref()
@@ -316,30 +1728,126 @@ pub fn format_mcp_tools_for_prompt(mcp: &McpManager, base_url: &str) -> String {
                 .collect::<Vec<_>>()
                 .join(" ");
             let params_block = format_params_block(tool.input_schema.as_object());
-            let curl_example = format!(
-                "curl -s -X POST '{}/mcp/call' -H 'Content-Type: application/json' -d '{{\"server\":\"{}\",\"tool\":\"{}\",\"arguments\":{{...}}}}'",
-                base, server_name, tool.name
-            );
+            let example = invoke_example(invoke, &server_name, &tool);
             let block = if params_block.is_empty() {
                 format!(
-                    "- **{}**\n  描述: {}\n  请求: {}",
-                    full_name, desc_short, curl_example
+                    "- **{}**{}\n  描述: {}\n  调用: {}",
+                    full_name, approval, desc_short, example
                 )
             } else {
                 format!(
-                    "- **{}**\n  描述: {}\n  params:\n{}\n  请求: {}",
-                    full_name, desc_short, params_block, curl_example
+                    "- **{}**{}\n  描述: {}\n  params:\n{}\n  调用: {}",
+                    full_name, approval, desc_short, params_block, example
                 )
             };
             lines.push(block);
         }
     }
-    if lines.is_empty() {
-        return String::new();
+    lines
+}
+
+/// `mash mcp invoke <server> <tool>` with a placeholder for each required parameter:
+/// `key=...` for strings, `key:=...` for everything else.
+fn invoke_example(invoke: &str, server: &str, tool: &McpTool) -> String {
+    let mut example = format!("{invoke} {server} {}", tool.name);
+    let schema = &tool.input_schema;
+    for key in schema["required"].as_array().into_iter().flatten() {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let separator = match schema["properties"][key]["type"].as_str() {
+            Some("string") => "=",
+            _ => ":=",
+        };
+        example.push_str(&format!(" {key}{separator}..."));
+    }
+    example
+}
+
+/// How bash should run this mash: `mash` when that is what `PATH` finds,
+/// otherwise the full path of the running executable.
+pub fn mash_command() -> String {
+    let Ok(exe) = std::env::current_exe() else {
+        return "mash".to_string();
+    };
+    let exe = exe.canonicalize().unwrap_or(exe);
+    let on_path = std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            dir.join("mash")
+                .canonicalize()
+                .is_ok_and(|found| found == exe)
+        })
+    });
+    match exe.display().to_string() {
+        _ if on_path => "mash".to_string(),
+        path if path.contains(char::is_whitespace) => format!("'{path}'"),
+        path => path,
+    }
+}
+
+/// One section per server, one line per tool: name and the first line of its description.
+fn compact_tool_index(mcp: &McpManager) -> Vec<String> {
+    let mut servers: Vec<(String, Vec<McpTool>)> = mcp.iter_servers_and_tools().collect();
+    servers.sort_by(|a, b| a.0.cmp(&b.0));
+    servers
+        .into_iter()
+        .filter(|(_, tools)| !tools.is_empty())
+        .map(|(server_name, tools)| {
+            let mut section = format!("### {server_name}（{} 个工具）", tools.len());
+            for tool in tools {
+                let summary = compact_summary(tool.description.as_deref());
+                let approval = approval_marker(mcp, &server_name, &tool.name);
+                section.push_str(&format!("\n- {}{approval}", tool.name));
+                if !summary.is_empty() {
+                    section.push_str(&format!(" — {summary}"));
+                }
+            }
+            section
+        })
+        .collect()
+}
+
+/// First non-empty line of a tool description, cut to [`COMPACT_SUMMARY_CHARS`].
+fn compact_summary(description: Option<&str>) -> String {
+    let summary = description
+        .and_then(|d| d.lines().map(str::trim).find(|l| !l.is_empty()))
+        .unwrap_or("");
+    if summary.chars().count() > COMPACT_SUMMARY_CHARS {
+        summary
+            .chars()
+            .take(COMPACT_SUMMARY_CHARS)
+            .collect::<String>()
+            + "…"
+    } else {
+        summary.to_string()
+    }
+}
+
+/// Routes for servers that expose resources or prompt templates.
+fn format_mcp_resources_for_prompt(mcp: &McpManager, base: &str, curl: &str) -> String {
+    let mut out = String::new();
+    let resources = mcp.servers_supporting("resources");
+    if !resources.is_empty() {
+        out.push_str(&format!(
+            "\n\n## MCP 资源\n\
+            以下 server 提供资源（文件、数据记录等）：{}\n\
+            - 列出资源: {curl} '{base}/mcp/resources?server=<server>'\n\
+            - 列出资源模板: {curl} '{base}/mcp/resources/templates?server=<server>'\n\
+            - 读取资源: {curl} -X POST '{base}/mcp/resources/read' -H 'Content-Type: application/json' -d '{{\"server\":\"<server>\",\"uri\":\"<uri>\"}}'",
+            resources.join(", ")
+        ));
     }
-    let header = "\n\n## MCP 工具（通过 bash 使用 curl 调用）\n\
-        需要调用以下工具时，请使用 bash 执行 curl 命令，POST 到 /mcp/call 接口。\n\n";
-    format!("{header}{}", lines.join("\n\n"))
+    let prompts = mcp.servers_supporting("prompts");
+    if !prompts.is_empty() {
+        out.push_str(&format!(
+            "\n\n## MCP 提示模板\n\
+            以下 server 提供提示模板：{}\n\
+            - 列出模板: {curl} '{base}/mcp/prompts?server=<server>'\n\
+            - 展开模板: {curl} -X POST '{base}/mcp/prompts/get' -H 'Content-Type: application/json' -d '{{\"server\":\"<server>\",\"prompt\":\"<name>\",\"arguments\":{{...}}}}'",
+            prompts.join(", ")
+        ));
+    }
+    out
 }
 
 fn format_params_block(input_schema: Option<&serde_json::Map<String, Value>>) -> String {
@@ -371,25 +1879,606 @@ fn format_params_block(input_schema: Option<&serde_json::Map<String, Value>>) ->
     out.join("\n")
 }
 
-/// 启动 MCP HTTP 服务，供 bash curl/wget 调用。
-pub async fn run_mcp_http_server(addr: SocketAddr, mcp: Arc<Mutex<McpManager>>) -> Result<()> {
+/// 启动 MCP HTTP 服务，供 bash curl/wget 调用。Every route requires `token`.
+/// Failed accepts on the unix socket are reported through `notify` (once per run
+/// of failures) and do not stop the bridge.
+pub async fn run_mcp_http_server(
+    listener: BridgeListener,
+    mcp: Arc<Mutex<McpManager>>,
+    token: String,
+    notify: impl Fn(String) + Send + 'static,
+) -> Result<()> {
     let app = Router::new()
+        .route("/health", get(health_handler))
+        .route("/mcp/servers", get(mcp_servers_handler))
+        .route("/mcp/tools", get(mcp_tools_handler))
+        .route("/mcp/tools/:server/:tool", get(mcp_tool_schema_handler))
         .route("/mcp/call", post(mcp_call_handler))
+        .route("/mcp/resources", get(mcp_resources_handler))
+        .route(
+            "/mcp/resources/templates",
+            get(mcp_resource_templates_handler),
+        )
+        .route("/mcp/resources/read", post(mcp_read_handler))
+        .route("/mcp/prompts", get(mcp_prompts_handler))
+        .route("/mcp/prompts/get", post(mcp_prompt_get_handler))
+        .layer(middleware::from_fn_with_state(Arc::new(token), guard))
         .with_state(mcp);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    match listener {
+        BridgeListener::Tcp(listener) => axum::serve(listener, app).await?,
+        BridgeListener::Unix(listener) => {
+            let mut failing = false;
+            loop {
+                let socket = match listener.accept().await {
+                    Ok((socket, _)) => socket,
+                    Err(e) => {
+                        if !failing {
+                            notify(format!("⚠ MCP bridge: accept failed: {e}"));
+                        }
+                        failing = true;
+                        // Errors such as EMFILE persist for a while; don't spin.
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                failing = false;
+                let service = TowerToHyperService::new(app.clone());
+                tokio::spawn(async move {
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(socket), service)
+                        .await;
+                });
+            }
+        }
+    }
     Ok(())
 }
 
+type HttpError = (StatusCode, String);
+
+/// Reject requests that are not from a local client holding the session token.
+async fn guard(
+    State(token): State<Arc<String>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, HttpError> {
+    check_request(request.headers(), &token)?;
+    Ok(next.run(request).await)
+}
+
+/// The checks of [`guard`]. The Host check defeats DNS rebinding, so a request
+/// without Host is refused too (curl, reqwest and browsers always send one);
+/// browsers always send Origin on cross-site requests, while curl never does.
+fn check_request(headers: &HeaderMap, token: &str) -> Result<(), HttpError> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v: &HeaderValue| v.to_str().ok())
+    };
+
+    match header(HOST) {
+        Some(host) if is_loopback_host(host) => {}
+        Some(host) => return Err((StatusCode::FORBIDDEN, format!("host '{host}' not allowed"))),
+        None => return Err((StatusCode::FORBIDDEN, "missing Host header".to_string())),
+    }
+    if let Some(origin) = header(ORIGIN)
+        && !origin
+            .split_once("://")
+            .is_some_and(|(_, host)| is_loopback_host(host))
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("origin '{origin}' not allowed"),
+        ));
+    }
+
+    let presented = header(AUTHORIZATION)
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            format!(
+                "missing or wrong bearer token (use -H \"Authorization: Bearer ${MCP_TOKEN_ENV}\")"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// `127.0.0.1`, `localhost` or `[::1]`, with or without a port.
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    matches!(name, "127.0.0.1" | "localhost" | "::1")
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn internal_error(e: anyhow::Error) -> HttpError {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Hold the manager lock only to look up the client, not for the request itself.
+/// Naming a lazy server here is what starts it.
+async fn lookup_client(
+    mcp: &Arc<Mutex<McpManager>>,
+    server: &str,
+) -> Result<Arc<McpClient>, HttpError> {
+    // 404 only for a server that is not configured; a known one that is down or
+    // failed to start is temporarily unavailable.
+    let status = match mcp.lock().await.configs.contains_key(server) {
+        true => StatusCode::SERVICE_UNAVAILABLE,
+        false => StatusCode::NOT_FOUND,
+    };
+    client_for(mcp, server).await.map_err(|e| (status, e))
+}
+
+/// The clients a list route should ask: the one named by `?server=`, or every
+/// connected server that advertised `capability` (all of them for `None`).
+async fn list_targets(
+    mcp: &Arc<Mutex<McpManager>>,
+    query: ServerQuery,
+    capability: Option<&str>,
+) -> Result<Vec<(String, Arc<McpClient>)>, HttpError> {
+    if let Some(server) = query.server {
+        let client = lookup_client(mcp, &server).await?;
+        return Ok(vec![(server, client)]);
+    }
+    let manager = mcp.lock().await;
+    Ok(manager
+        .clients
+        .iter()
+        .filter(|(_, c)| capability.is_none_or(|cap| c.supports(cap)))
+        .map(|(n, c)| (n.clone(), Arc::clone(c)))
+        .collect())
+}
+
+/// Tag each listed item with the server it came from.
+fn with_server(server: &str, items: Vec<Value>) -> impl Iterator<Item = Value> + '_ {
+    items.into_iter().map(move |mut item| {
+        if let Some(obj) = item.as_object_mut() {
+            obj.insert("server".to_string(), json!(server));
+        }
+        item
+    })
+}
+
+async fn health_handler(State(mcp): State<Arc<Mutex<McpManager>>>) -> Json<Value> {
+    let manager = mcp.lock().await;
+    let enabled = manager.configs.values().filter(|c| !c.disabled).count();
+    Json(json!({
+        "status": "ok",
+        "servers": { "connected": manager.clients.len(), "enabled": enabled },
+    }))
+}
+
+/// Every configured server with its transport, health and what it offers.
+async fn mcp_servers_handler(State(mcp): State<Arc<Mutex<McpManager>>>) -> Json<Vec<Value>> {
+    let manager = mcp.lock().await;
+    let mut names: Vec<&String> = manager.configs.keys().collect();
+    names.sort();
+    let servers = names
+        .into_iter()
+        .map(|name| {
+            let health = manager.health(name);
+            let client = manager.clients.get(name);
+            json!({
+                "name": name,
+                "transport": manager.configs[name].describe(),
+                "state": health.map(|h| h.state()).unwrap_or("connecting"),
+                "error": health.and_then(|h| h.error()),
+                "tools": client.map(|c| c.tool_count()),
+                "resources": client.is_some_and(|c| c.supports("resources")),
+                "prompts": client.map(|c| c.prompts().len()),
+            })
+        })
+        .collect();
+    Json(servers)
+}
+
+/// Tool names and descriptions; the full schema is served per tool.
+async fn mcp_tools_handler(
+    State(mcp): State<Arc<Mutex<McpManager>>>,
+    Query(query): Query<ServerQuery>,
+) -> Result<Json<Vec<Value>>, HttpError> {
+    let mut all = Vec::new();
+    for (server, client) in list_targets(&mcp, query, None).await? {
+        let items = client
+            .tools()
+            .into_iter()
+            .map(|t| json!({ "name": t.name, "description": t.description }))
+            .collect();
+        all.extend(with_server(&server, items));
+    }
+    all.sort_by(|a, b| {
+        (a["server"].as_str(), a["name"].as_str()).cmp(&(b["server"].as_str(), b["name"].as_str()))
+    });
+    Ok(Json(all))
+}
+
+async fn mcp_tool_schema_handler(
+    State(mcp): State<Arc<Mutex<McpManager>>>,
+    UrlPath((server, tool)): UrlPath<(String, String)>,
+) -> Result<Json<Value>, HttpError> {
+    let client = lookup_client(&mcp, &server).await?;
+    let found = client.tools().into_iter().find(|t| t.name == tool);
+    let Some(found) = found else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("MCP server '{server}' has no tool '{tool}'"),
+        ));
+    };
+    Ok(Json(json!({
+        "server": server,
+        "name": found.name,
+        "description": found.description,
+        "inputSchema": found.input_schema,
+    })))
+}
+
+/// Tool-level failures (`isError`) answer 422 with a JSON envelope, so callers can
+/// tell them apart from transport/protocol errors (404/500, plain text).
+/// Arguments that do not match the tool's `inputSchema` answer 400 before the
+/// server is called, with a JSON Pointer to each offending field. Tools hidden by
+/// `includeTools`/`excludeTools`, or denied by the user, answer 403.
 async fn mcp_call_handler(
     State(mcp): State<Arc<Mutex<McpManager>>>,
-    Json(body): Json<McpCallRequest>,
-) -> Result<String, (StatusCode, String)> {
-    let full_name = format!("mcp__{}__{}", body.server, body.tool);
-    let mut guard = mcp.lock().await;
-    guard
-        .call_tool(&full_name, &body.arguments)
+    Query(query): Query<CallQuery>,
+    headers: HeaderMap,
+    Json(mut body): Json<McpCallRequest>,
+) -> Result<Response, HttpError> {
+    let (config, host) = {
+        let manager = mcp.lock().await;
+        (
+            manager.configs.get(&body.server).cloned(),
+            manager.host.clone(),
+        )
+    };
+    if let Some(config) = &config
+        && !config.tool_filter().allows(&body.tool)
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!(
+                "tool '{}' of MCP server '{}' is disabled by includeTools/excludeTools",
+                body.tool, body.server
+            ),
+        ));
+    }
+    let client = lookup_client(&mcp, &body.server).await?;
+    let Some(tool) = client.tools().into_iter().find(|t| t.name == body.tool) else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("MCP server '{}' has no tool '{}'", body.server, body.tool),
+        ));
+    };
+    if body.arguments.is_null() {
+        body.arguments = json!({});
+    }
+    let errors = schema::validate(&tool.input_schema, &body.arguments);
+    if !errors.is_empty() {
+        let envelope = json!({
+            "error": "invalid arguments",
+            "server": body.server,
+            "tool": body.tool,
+            "errors": errors,
+            "inputSchema": tool.input_schema,
+        });
+        return Ok((StatusCode::BAD_REQUEST, Json(envelope)).into_response());
+    }
+
+    if config.is_some_and(|c| c.needs_approval(&body.tool)) {
+        let denied = |reason: &str| {
+            Err((
+                StatusCode::FORBIDDEN,
+                format!("call to {}/{} {reason}", body.server, body.tool),
+            ))
+        };
+        if host.approvals.is_none() {
+            return denied("needs user approval, which is only available in the TUI");
+        }
+        let kind = ApprovalKind::Tool {
+            tool: body.tool.clone(),
+            arguments: body.arguments.clone(),
+        };
+        if !host.approve(&body.server, kind).await {
+            return denied("was denied by the user; do not retry it without asking");
+        }
+    }
+
+    let result = client
+        .call_tool(&body.tool, body.arguments)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        .map_err(internal_error)?;
+
+    if result.is_error {
+        let envelope = json!({
+            "isError": true,
+            "server": body.server,
+            "tool": body.tool,
+            "error": result.text,
+        });
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(envelope)).into_response());
+    }
+
+    let wants_json = query.structured
+        || headers
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("application/json"));
+    if wants_json {
+        let body = result
+            .structured
+            .unwrap_or_else(|| json!({ "text": result.text }));
+        return Ok(Json(body).into_response());
+    }
+    Ok(result.text.into_response())
+}
+
+async fn mcp_resources_handler(
+    State(mcp): State<Arc<Mutex<McpManager>>>,
+    Query(query): Query<ServerQuery>,
+) -> Result<Json<Vec<Value>>, HttpError> {
+    let mut all = Vec::new();
+    for (server, client) in list_targets(&mcp, query, Some("resources")).await? {
+        let items = client.list_resources().await.map_err(internal_error)?;
+        all.extend(with_server(&server, items));
+    }
+    Ok(Json(all))
+}
+
+async fn mcp_resource_templates_handler(
+    State(mcp): State<Arc<Mutex<McpManager>>>,
+    Query(query): Query<ServerQuery>,
+) -> Result<Json<Vec<Value>>, HttpError> {
+    let mut all = Vec::new();
+    for (server, client) in list_targets(&mcp, query, Some("resources")).await? {
+        let items = client
+            .list_resource_templates()
+            .await
+            .map_err(internal_error)?;
+        all.extend(with_server(&server, items));
+    }
+    Ok(Json(all))
+}
+
+async fn mcp_read_handler(
+    State(mcp): State<Arc<Mutex<McpManager>>>,
+    Json(body): Json<McpReadRequest>,
+) -> Result<String, HttpError> {
+    let client = lookup_client(&mcp, &body.server).await?;
+    client
+        .read_resource(&body.uri)
+        .await
+        .map_err(internal_error)
+}
+
+async fn mcp_prompts_handler(
+    State(mcp): State<Arc<Mutex<McpManager>>>,
+    Query(query): Query<ServerQuery>,
+) -> Result<Json<Vec<Value>>, HttpError> {
+    let mut all = Vec::new();
+    for (server, client) in list_targets(&mcp, query, Some("prompts")).await? {
+        let items = client
+            .prompts()
+            .iter()
+            .map(|p| serde_json::to_value(p).unwrap_or_default())
+            .collect();
+        all.extend(with_server(&server, items));
+    }
+    Ok(Json(all))
+}
+
+async fn mcp_prompt_get_handler(
+    State(mcp): State<Arc<Mutex<McpManager>>>,
+    Json(body): Json<McpPromptRequest>,
+) -> Result<String, HttpError> {
+    let client = lookup_client(&mcp, &body.server).await?;
+    client
+        .get_prompt(&body.prompt, &body.arguments)
+        .await
+        .map_err(internal_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn status(pairs: &[(&'static str, &str)]) -> Option<StatusCode> {
+        check_request(&headers(pairs), "tok")
+            .err()
+            .map(|(status, _)| status)
+    }
+
+    #[test]
+    fn loopback_hosts() {
+        for host in [
+            "127.0.0.1",
+            "127.0.0.1:31415",
+            "localhost",
+            "localhost:8",
+            "[::1]",
+            "[::1]:31415",
+        ] {
+            assert!(is_loopback_host(host), "{host}");
+        }
+        for host in [
+            "localhost.evil.com",
+            "127.0.0.1.nip.io",
+            "evil.com:31415",
+            "[::2]:31415",
+            "",
+        ] {
+            assert!(!is_loopback_host(host), "{host}");
+        }
+    }
+
+    #[test]
+    fn guard_accepts_a_local_request_with_the_token() {
+        let ok = [("host", "127.0.0.1:31415"), ("authorization", "Bearer tok")];
+        assert_eq!(status(&ok), None);
+        let ipv6 = [("host", "[::1]:31415"), ("authorization", "Bearer tok")];
+        assert_eq!(status(&ipv6), None);
+        let local_origin = [
+            ("host", "localhost"),
+            ("origin", "http://localhost:31415"),
+            ("authorization", "Bearer tok"),
+        ];
+        assert_eq!(status(&local_origin), None);
+    }
+
+    #[test]
+    fn guard_rejects_foreign_or_missing_hosts() {
+        for host in ["localhost.evil.com", "127.0.0.1.nip.io:31415"] {
+            let request = [("host", host), ("authorization", "Bearer tok")];
+            assert_eq!(status(&request), Some(StatusCode::FORBIDDEN), "{host}");
+        }
+        let no_host = [("authorization", "Bearer tok")];
+        assert_eq!(status(&no_host), Some(StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn guard_rejects_foreign_and_null_origins() {
+        for origin in ["null", "http://evil.com", "http://localhost.evil.com"] {
+            let request = [
+                ("host", "127.0.0.1:31415"),
+                ("origin", origin),
+                ("authorization", "Bearer tok"),
+            ];
+            assert_eq!(status(&request), Some(StatusCode::FORBIDDEN), "{origin}");
+        }
+    }
+
+    #[test]
+    fn guard_requires_the_exact_token() {
+        let missing = [("host", "127.0.0.1")];
+        assert_eq!(status(&missing), Some(StatusCode::UNAUTHORIZED));
+        for value in [
+            "Bearer wrong",
+            "Bearer to",
+            "Bearer tokk",
+            "tok",
+            "Basic tok",
+        ] {
+            let request = [("host", "127.0.0.1"), ("authorization", value)];
+            assert_eq!(status(&request), Some(StatusCode::UNAUTHORIZED), "{value}");
+        }
+    }
+
+    #[test]
+    fn constant_time_eq_compares_lengths_and_bytes() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn private_dir_is_created_with_mode_0700() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = tempfile::tempdir().unwrap();
+        let dir = base.path().join("mash");
+        ensure_private_dir(&dir).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        // An existing private directory is reused.
+        ensure_private_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn private_dir_rejects_shared_modes_and_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = tempfile::tempdir().unwrap();
+        let shared = base.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(ensure_private_dir(&shared).is_err());
+
+        let target = base.path().join("target");
+        ensure_private_dir(&target).unwrap();
+        let link = base.path().join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        assert!(ensure_private_dir(&link).is_err());
+    }
+
+    fn approving(patterns: &[&str]) -> McpServerConfig {
+        serde_json::from_value(json!({ "command": "x", "approveTools": patterns })).unwrap()
+    }
+
+    #[test]
+    fn direct_call_of_an_unguarded_tool_is_allowed() {
+        let config = approving(&["delete_*"]);
+        assert!(
+            config
+                .check_direct_call("list_repos", false, true, false)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn direct_call_of_an_approval_tool_is_refused_in_a_session() {
+        let config = approving(&["delete_*"]);
+        assert!(
+            config
+                .check_direct_call("delete_repo", true, true, true)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn direct_call_of_an_approval_tool_needs_yes_at_a_terminal() {
+        let config = approving(&["delete_*"]);
+        assert!(
+            config
+                .check_direct_call("delete_repo", false, false, true)
+                .is_err()
+        );
+        assert!(
+            config
+                .check_direct_call("delete_repo", true, false, false)
+                .is_err()
+        );
+        assert!(
+            config
+                .check_direct_call("delete_repo", true, false, true)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn compact_summary_uses_the_first_non_empty_line() {
+        assert_eq!(compact_summary(None), "");
+        assert_eq!(
+            compact_summary(Some("\n  \n  Read a file.\nMore.")),
+            "Read a file."
+        );
+    }
+
+    #[test]
+    fn compact_summary_truncates_by_chars() {
+        let exact = "a".repeat(COMPACT_SUMMARY_CHARS);
+        assert_eq!(compact_summary(Some(&exact)), exact);
+
+        let long = "读".repeat(COMPACT_SUMMARY_CHARS + 1);
+        let summary = compact_summary(Some(&long));
+        assert_eq!(summary.chars().count(), COMPACT_SUMMARY_CHARS + 1);
+        assert!(summary.ends_with('…'));
+        assert!(summary.starts_with(&"读".repeat(COMPACT_SUMMARY_CHARS)));
+    }
 }
@@ -0,0 +1,420 @@
+//! Reading and editing `mcp.json` for the `mash mcp add/remove/enable/disable/import`
+//! subcommands.
+//!
+//! Servers come from two scopes, merged by name with the project winning:
+//! `~/.mash/mcp.json` (user) and `<project>/.mash/mcp.json` (project, can be
+//! committed). Edits go through `serde_json::Value` so keys mash does not know
+//! about survive a rewrite.
+//!
+//! A project server is only started once the user has approved it; the answers
+//! are kept in `~/.mash/mcp-approvals.json`.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value, json};
+
+use super::SERVER_CONFIG_KEYS;
+use crate::core::config::{mash_config_path, parse_json, project_dir};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McpScope {
+    User,
+    Project,
+}
+
+impl McpScope {
+    pub fn path(self) -> Result<PathBuf> {
+        match self {
+            McpScope::User => mash_config_path("mcp.json"),
+            McpScope::Project => match project_dir() {
+                Some(dir) => Ok(dir.join(".mash").join("mcp.json")),
+                None => bail!("could not determine the project directory"),
+            },
+        }
+    }
+}
+
+impl FromStr for McpScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(McpScope::User),
+            "project" => Ok(McpScope::Project),
+            _ => Err(format!("unknown scope '{s}' (expected user or project)")),
+        }
+    }
+}
+
+/// mcp.json files in merge order (user first), whether or not they exist.
+pub fn config_paths() -> Vec<(McpScope, PathBuf)> {
+    [McpScope::User, McpScope::Project]
+        .into_iter()
+        .filter_map(|scope| Some((scope, scope.path().ok()?)))
+        .collect()
+}
+
+/// Add server `name` with the given mcp.json `entry`. Replaces an existing
+/// entry only when `replace` is set.
+pub fn add_server(scope: McpScope, name: &str, entry: Value, replace: bool) -> Result<PathBuf> {
+    let path = scope.path()?;
+    add_server_at(&path, name, entry, replace)?;
+    Ok(path)
+}
+
+fn add_server_at(path: &Path, name: &str, entry: Value, replace: bool) -> Result<()> {
+    let mut file = read(path)?;
+    let servers = servers_mut(&mut file, path)?;
+    if servers.contains_key(name) && !replace {
+        bail!(
+            "MCP server '{name}' already exists in {}; remove it first",
+            path.display()
+        );
+    }
+    servers.insert(name.to_string(), entry);
+    write(path, &file)
+}
+
+pub fn remove_server(scope: Option<McpScope>, name: &str) -> Result<PathBuf> {
+    let path = locate(scope, name)?;
+    remove_server_at(&path, name)?;
+    Ok(path)
+}
+
+fn remove_server_at(path: &Path, name: &str) -> Result<()> {
+    let mut file = read(path)?;
+    servers_mut(&mut file, path)?.remove(name);
+    write(path, &file)
+}
+
+/// Set `disabled` on server `name`; enabling drops the key instead of writing `false`.
+pub fn set_disabled(scope: Option<McpScope>, name: &str, disabled: bool) -> Result<PathBuf> {
+    let path = locate(scope, name)?;
+    set_disabled_at(&path, name, disabled)?;
+    Ok(path)
+}
+
+fn set_disabled_at(path: &Path, name: &str, disabled: bool) -> Result<()> {
+    let mut file = read(path)?;
+    let servers = servers_mut(&mut file, path)?;
+    let Some(Value::Object(entry)) = servers.get_mut(name) else {
+        bail!("{}: mcpServers.{name} is not an object", path.display());
+    };
+    if disabled {
+        entry.insert("disabled".to_string(), json!(true));
+    } else {
+        entry.remove("disabled");
+    }
+    write(path, &file)
+}
+
+/// The file that defines `name`: the one for `scope`, or the only scope that
+/// has it when no scope is given.
+fn locate(scope: Option<McpScope>, name: &str) -> Result<PathBuf> {
+    let candidates = match scope {
+        Some(scope) => vec![scope.path()?],
+        None => config_paths().into_iter().map(|(_, path)| path).collect(),
+    };
+    locate_in(candidates, name)
+}
+
+fn locate_in(candidates: Vec<PathBuf>, name: &str) -> Result<PathBuf> {
+    let mut found = Vec::new();
+    for path in candidates {
+        if read(&path)?["mcpServers"].get(name).is_some() {
+            found.push(path);
+        }
+    }
+    match found.len() {
+        0 => bail!("MCP server '{name}' not found in mcp.json"),
+        1 => Ok(found.remove(0)),
+        _ => {
+            bail!("MCP server '{name}' is defined in both user and project mcp.json; pass --scope")
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Value> {
+    if !path.exists() {
+        return Ok(json!({}));
+    }
+    let value: Value = parse_json(path, &std::fs::read_to_string(path)?)?;
+    if !value.is_object() {
+        bail!("{}: top-level value is not an object", path.display());
+    }
+    Ok(value)
+}
+
+fn write(path: &Path, value: &Value) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(value)? + "\n")?;
+    Ok(())
+}
+
+fn servers_mut<'a>(file: &'a mut Value, path: &Path) -> Result<&'a mut Map<String, Value>> {
+    let servers = file
+        .as_object_mut()
+        .expect("checked in read")
+        .entry("mcpServers")
+        .or_insert_with(|| json!({}));
+    match servers {
+        Value::Object(servers) => Ok(servers),
+        _ => bail!("{}: mcpServers is not an object", path.display()),
+    }
+}
+
+// ── Project server approvals ─────────────────────────────────────
+
+/// The user's earlier answer for project server `name`, if it was given for this
+/// exact `entry`; a changed entry (e.g. a new command) is asked about again.
+pub fn project_server_decision(name: &str, entry: &Value) -> Option<bool> {
+    let path = mash_config_path("mcp-approvals.json").ok()?;
+    let project = project_dir()?;
+    let approvals = read(&path).ok()?;
+    let answer = &approvals[project.to_string_lossy().as_ref()][name];
+    (answer["entry"] == *entry).then(|| answer["approved"].as_bool())?
+}
+
+/// Remember the answer for project server `name` in `~/.mash/mcp-approvals.json`.
+pub fn remember_project_server(name: &str, entry: &Value, approved: bool) -> Result<PathBuf> {
+    let path = mash_config_path("mcp-approvals.json")?;
+    let Some(project) = project_dir() else {
+        bail!("could not determine the project directory");
+    };
+    let mut approvals = read(&path)?;
+    let projects = approvals.as_object_mut().expect("checked in read");
+    let servers = projects
+        .entry(project.to_string_lossy())
+        .or_insert_with(|| json!({}));
+    let Some(servers) = servers.as_object_mut() else {
+        bail!("{}: {} is not an object", path.display(), project.display());
+    };
+    servers.insert(
+        name.to_string(),
+        json!({ "approved": approved, "entry": entry }),
+    );
+    write(&path, &approvals)?;
+    Ok(path)
+}
+
+// ── Import ──────────────────────────────────────────────────────
+
+/// Other MCP hosts whose server definitions `mash mcp import` understands.
+#[derive(Debug, Clone, Copy)]
+pub enum ImportSource {
+    ClaudeDesktop,
+    ClaudeCode,
+}
+
+impl FromStr for ImportSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "claude-desktop" => Ok(ImportSource::ClaudeDesktop),
+            "claude-code" => Ok(ImportSource::ClaudeCode),
+            _ => Err(format!(
+                "unknown source '{s}' (expected claude-desktop or claude-code)"
+            )),
+        }
+    }
+}
+
+/// Server entries found for `source`, read from `from` instead of the default
+/// locations when given. Keys mash does not understand are dropped.
+pub fn import_servers(source: ImportSource, from: Option<PathBuf>) -> Result<Vec<(String, Value)>> {
+    let project = project_dir();
+    match (source, from) {
+        (_, Some(path)) => {
+            if !path.exists() {
+                bail!("{} does not exist", path.display());
+            }
+            Ok(file_servers(&read(&path)?, project.as_deref()))
+        }
+        (ImportSource::ClaudeDesktop, None) => {
+            let Some(dir) = dirs::config_dir() else {
+                bail!("could not determine the config directory");
+            };
+            let path = dir.join("Claude").join("claude_desktop_config.json");
+            if !path.exists() {
+                bail!("Claude Desktop config not found at {}", path.display());
+            }
+            Ok(entries(&read(&path)?["mcpServers"]))
+        }
+        (ImportSource::ClaudeCode, None) => {
+            let Some(home) = dirs::home_dir() else {
+                bail!("could not determine home directory");
+            };
+            claude_code_servers(&home, project.as_deref())
+        }
+    }
+}
+
+/// `mcpServers` of a config file, plus those kept for `project` under
+/// `projects.<dir>` (as ~/.claude.json does).
+fn file_servers(file: &Value, project: Option<&Path>) -> Vec<(String, Value)> {
+    let mut servers = entries(&file["mcpServers"]);
+    if let Some(dir) = project {
+        let project = &file["projects"][dir.to_string_lossy().as_ref()];
+        servers.extend(entries(&project["mcpServers"]));
+    }
+    servers
+}
+
+/// Claude Code's servers: `~/.claude.json` (global and for `project`), then the
+/// project's own `.mcp.json`.
+fn claude_code_servers(home: &Path, project: Option<&Path>) -> Result<Vec<(String, Value)>> {
+    let mut servers = file_servers(&read(&home.join(".claude.json"))?, project);
+    if let Some(dir) = project {
+        servers.extend(entries(&read(&dir.join(".mcp.json"))?["mcpServers"]));
+    }
+    Ok(servers)
+}
+
+fn entries(servers: &Value) -> Vec<(String, Value)> {
+    let Some(servers) = servers.as_object() else {
+        return Vec::new();
+    };
+    servers
+        .iter()
+        .filter_map(|(name, entry)| {
+            let entry: Map<String, Value> = entry
+                .as_object()?
+                .iter()
+                .filter(|(key, _)| SERVER_CONFIG_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            Some((name.clone(), Value::Object(entry)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers(path: &Path) -> Value {
+        read(path).unwrap()["mcpServers"].clone()
+    }
+
+    #[test]
+    fn add_keeps_other_keys_and_refuses_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".mash").join("mcp.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, r#"{ "comment": "mine", "mcpServers": {} }"#).unwrap();
+
+        add_server_at(&path, "fs", json!({ "command": "fs" }), false).unwrap();
+        assert!(add_server_at(&path, "fs", json!({ "command": "other" }), false).is_err());
+        assert_eq!(servers(&path)["fs"]["command"], "fs");
+        add_server_at(&path, "fs", json!({ "command": "other" }), true).unwrap();
+        assert_eq!(servers(&path)["fs"]["command"], "other");
+        assert_eq!(read(&path).unwrap()["comment"], "mine");
+    }
+
+    #[test]
+    fn disable_enable_and_remove_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mcp.json");
+        add_server_at(&path, "fs", json!({ "command": "fs", "lazy": true }), false).unwrap();
+
+        set_disabled_at(&path, "fs", true).unwrap();
+        assert_eq!(servers(&path)["fs"]["disabled"], true);
+        set_disabled_at(&path, "fs", false).unwrap();
+        assert_eq!(
+            servers(&path)["fs"],
+            json!({ "command": "fs", "lazy": true })
+        );
+        assert!(set_disabled_at(&path, "nosuch", true).is_err());
+
+        remove_server_at(&path, "fs").unwrap();
+        assert_eq!(servers(&path), json!({}));
+    }
+
+    #[test]
+    fn locate_needs_a_unique_definition() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("user.json");
+        let project = dir.path().join("project.json");
+        add_server_at(&user, "both", json!({}), false).unwrap();
+        add_server_at(&project, "both", json!({}), false).unwrap();
+        add_server_at(&project, "mine", json!({}), false).unwrap();
+        let candidates = || {
+            vec![
+                user.clone(),
+                project.clone(),
+                dir.path().join("missing.json"),
+            ]
+        };
+
+        assert_eq!(locate_in(candidates(), "mine").unwrap(), project);
+        assert!(locate_in(candidates(), "both").is_err());
+        assert!(locate_in(candidates(), "nosuch").is_err());
+        assert_eq!(locate_in(vec![user.clone()], "both").unwrap(), user);
+    }
+
+    #[test]
+    fn import_keeps_known_keys_and_project_servers() {
+        let file = json!({
+            "mcpServers": {
+                "fs": { "command": "npx", "args": ["fs"], "env": { "A": "1" }, "icon": "x" },
+                "broken": "not an object",
+            },
+            "projects": {
+                "/work/repo": { "mcpServers": { "db": { "type": "http", "url": "http://db" } } },
+                "/work/other": { "mcpServers": { "other": { "command": "o" } } },
+            },
+        });
+        let imported = file_servers(&file, Some(Path::new("/work/repo")));
+        assert_eq!(
+            imported,
+            [
+                (
+                    "fs".to_string(),
+                    json!({ "command": "npx", "args": ["fs"], "env": { "A": "1" } })
+                ),
+                (
+                    "db".to_string(),
+                    json!({ "type": "http", "url": "http://db" })
+                ),
+            ]
+        );
+        assert_eq!(file_servers(&file, None).len(), 1);
+    }
+
+    #[test]
+    fn claude_code_import_reads_global_and_project_files() {
+        let home = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let key = project.path().to_string_lossy().to_string();
+        let global = json!({
+            "mcpServers": { "global": { "command": "g" } },
+            "projects": { key: { "mcpServers": { "local": { "command": "l" } } } },
+        });
+        std::fs::write(home.path().join(".claude.json"), global.to_string()).unwrap();
+        std::fs::write(
+            project.path().join(".mcp.json"),
+            r#"{ "mcpServers": { "shared": { "command": "s" } } }"#,
+        )
+        .unwrap();
+
+        let names: Vec<String> = claude_code_servers(home.path(), Some(project.path()))
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["global", "local", "shared"]);
+
+        // Neither file is required.
+        let empty = tempfile::tempdir().unwrap();
+        assert!(
+            claude_code_servers(empty.path(), Some(empty.path()))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
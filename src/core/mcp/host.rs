@@ -0,0 +1,232 @@
+//! Requests an MCP server sends to mash as its client: `roots/list` (which
+//! directories the session works in) and `sampling/createMessage` (a completion
+//! from the model the session is using).
+//!
+//! Sampling is only offered when a model was handed to the manager (the TUI),
+//! and by default each request waits for the user's y/n like an `approveTools`
+//! call. Per server, `"sampling": "allow"` skips the question and `"deny"`
+//! stops advertising the capability.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::core::api::{AnthropicClient, ContentBlock, Message, MessageContent};
+use crate::core::config::project_dir;
+
+/// JSON-RPC error code for a request the user (or the config) refused.
+const REJECTED: i64 = -1;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// `sampling` in a server's mcp.json entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingPolicy {
+    /// Ask the TUI user before each request.
+    #[default]
+    Ask,
+    Allow,
+    Deny,
+}
+
+/// What the user is asked to approve.
+pub enum ApprovalKind {
+    /// A call to a tool matched by `approveTools`.
+    Tool { tool: String, arguments: Value },
+    /// A `sampling/createMessage` request: its `systemPrompt`, the number of
+    /// messages and the last of them as `prompt`.
+    Sampling {
+        system: String,
+        messages: usize,
+        prompt: String,
+        max_tokens: u32,
+    },
+    /// Starting a server from the project's `.mash/mcp.json` for the first time.
+    /// `replaces_user` is set when it shadows a user server of the same name.
+    Server {
+        command: String,
+        sampling: SamplingPolicy,
+        replaces_user: bool,
+    },
+}
+
+/// A request waiting for the TUI user's decision.
+pub struct ApprovalRequest {
+    pub server: String,
+    pub kind: ApprovalKind,
+    pub reply: oneshot::Sender<bool>,
+}
+
+/// What mash offers the servers it connects to, shared by all clients.
+#[derive(Clone, Default)]
+pub struct ClientHost {
+    /// Serves `sampling/createMessage`; without one sampling is not advertised.
+    pub model: Option<Arc<AnthropicClient>>,
+    /// Where requests needing approval are sent; without one they are refused.
+    pub approvals: Option<mpsc::UnboundedSender<ApprovalRequest>>,
+}
+
+impl ClientHost {
+    /// `capabilities` for the `initialize` request of a server with `sampling`.
+    pub fn capabilities(&self, sampling: SamplingPolicy) -> Value {
+        let mut capabilities = json!({ "roots": {} });
+        if self.model.is_some() && sampling != SamplingPolicy::Deny {
+            capabilities["sampling"] = json!({});
+        }
+        capabilities
+    }
+
+    /// Ask the user about `kind`; false when nobody can answer.
+    pub async fn approve(&self, server: &str, kind: ApprovalKind) -> bool {
+        let Some(approvals) = &self.approvals else {
+            return false;
+        };
+        let (reply, decision) = oneshot::channel();
+        let request = ApprovalRequest {
+            server: server.to_string(),
+            kind,
+            reply,
+        };
+        approvals.send(request).is_ok() && decision.await.unwrap_or(false)
+    }
+
+    /// Answer one request from `server`; the error is a JSON-RPC code and message.
+    pub async fn handle_request(
+        &self,
+        server: &str,
+        sampling: SamplingPolicy,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, (i64, String)> {
+        match method {
+            "ping" => Ok(json!({})),
+            "roots/list" => Ok(json!({ "roots": roots() })),
+            "sampling/createMessage" => self.create_message(server, sampling, params).await,
+            _ => Err((METHOD_NOT_FOUND, format!("method not found: {method}"))),
+        }
+    }
+
+    async fn create_message(
+        &self,
+        server: &str,
+        sampling: SamplingPolicy,
+        params: &Value,
+    ) -> Result<Value, (i64, String)> {
+        let model = match &self.model {
+            Some(model) if sampling != SamplingPolicy::Deny => model,
+            _ => return Err((METHOD_NOT_FOUND, "sampling is not enabled".to_string())),
+        };
+        let messages = sampling_messages(params).map_err(|e| (INVALID_PARAMS, e))?;
+        let requested = match params["maxTokens"].as_u64() {
+            Some(0) => return Err((INVALID_PARAMS, "'maxTokens' must be at least 1".to_string())),
+            Some(max_tokens) => u32::try_from(max_tokens).unwrap_or(u32::MAX),
+            None => u32::MAX,
+        };
+        let system = params["systemPrompt"].as_str().unwrap_or_default();
+
+        if sampling == SamplingPolicy::Ask {
+            let prompt = match messages.last().map(|m| &m.content) {
+                Some(MessageContent::Text(text)) => text.clone(),
+                _ => String::new(),
+            };
+            let kind = ApprovalKind::Sampling {
+                system: system.to_string(),
+                messages: messages.len(),
+                prompt,
+                max_tokens: model.active_config().max_tokens.min(requested),
+            };
+            if !self.approve(server, kind).await {
+                return Err((REJECTED, "user rejected sampling request".to_string()));
+            }
+        }
+
+        // The session's client, so sampling fails over like the conversation does.
+        let (response, config) = model
+            .sample(system, requested, &messages)
+            .await
+            .map_err(|e| (INTERNAL_ERROR, e.to_string()))?;
+        let text = response
+            .content
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let stop_reason = match response.stop_reason.as_deref() {
+            Some("max_tokens") => "maxTokens",
+            Some("stop_sequence") => "stopSequence",
+            _ => "endTurn",
+        };
+        Ok(json!({
+            "role": "assistant",
+            "content": { "type": "text", "text": text },
+            "model": config.model,
+            "stopReason": stop_reason,
+        }))
+    }
+}
+
+/// The request's messages as API messages. Only text content can be forwarded;
+/// `content` may be one block or (newer protocol versions) a list of blocks.
+fn sampling_messages(params: &Value) -> Result<Vec<Message>, String> {
+    let Some(messages) = params["messages"].as_array() else {
+        return Err("'messages' is required".to_string());
+    };
+    messages
+        .iter()
+        .map(|message| {
+            let role = match message["role"].as_str() {
+                Some(role @ ("user" | "assistant")) => role,
+                _ => return Err(format!("unsupported role {}", message["role"])),
+            };
+            let blocks = match &message["content"] {
+                Value::Array(blocks) => blocks.iter().collect(),
+                block => vec![block],
+            };
+            let mut text = Vec::new();
+            for block in blocks {
+                match (block["type"].as_str(), block["text"].as_str()) {
+                    (Some("text"), Some(t)) => text.push(t),
+                    (kind, _) => {
+                        return Err(format!(
+                            "unsupported content type {} (only text is supported)",
+                            kind.unwrap_or("(none)")
+                        ));
+                    }
+                }
+            }
+            Ok(Message {
+                role: role.to_string(),
+                content: MessageContent::Text(text.join("\n")),
+            })
+        })
+        .collect()
+}
+
+/// The working directory, and the project (git) root when it is a different one.
+fn roots() -> Vec<Value> {
+    let mut dirs = Vec::new();
+    if let Ok(cwd) = std::env::current_dir() {
+        dirs.push(cwd);
+    }
+    if let Some(project) = project_dir()
+        && !dirs.contains(&project)
+    {
+        dirs.push(project);
+    }
+    dirs.iter().filter_map(|dir| root(dir)).collect()
+}
+
+fn root(dir: &Path) -> Option<Value> {
+    let uri = Url::from_file_path(dir).ok()?;
+    let name = dir.file_name()?.to_string_lossy();
+    Some(json!({ "uri": uri.as_str(), "name": name }))
+}
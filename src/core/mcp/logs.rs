@@ -0,0 +1,148 @@
+//! Per-server stderr logs for stdio MCP servers: `~/.mash/logs/mcp/<name>.log`.
+//!
+//! Each log is rotated to `<name>.log.1` … `<name>.log.3` once it grows past
+//! [`MAX_LOG_BYTES`]. The last few lines are also kept in memory so connection
+//! errors can say why a server died. `notifications/message` log messages from
+//! any server go to the same file.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::ChildStderr;
+use tokio::sync::watch;
+
+/// Size at which a log is rotated.
+pub const MAX_LOG_BYTES: u64 = 1024 * 1024;
+/// Rotated generations kept next to the live log.
+const ROTATED_LOGS: u32 = 3;
+/// stderr lines kept in memory for error messages.
+const TAIL_LINES: usize = 10;
+
+/// Log file of server `name`. Characters other than ASCII letters, digits, `-`, `_`
+/// and `.` become `_`, so a name such as `../x` stays inside the logs directory.
+pub fn log_path(name: &str) -> Result<PathBuf> {
+    let file: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+    Ok(crate::core::config::mash_config_path("logs")?
+        .join("mcp")
+        .join(format!("{file}.log")))
+}
+
+/// The stderr of one running server, copied to its log file by a background task.
+pub struct StderrLog {
+    tail: Arc<Mutex<VecDeque<String>>>,
+    /// Becomes `true` once stderr reached EOF (the process exited).
+    done: watch::Receiver<bool>,
+}
+
+impl StderrLog {
+    /// Start copying `stderr` to the log of server `name`, after a `header` line.
+    pub fn capture(name: &str, stderr: ChildStderr, header: String) -> Self {
+        let tail = Arc::new(Mutex::new(VecDeque::with_capacity(TAIL_LINES)));
+        let (done_tx, done) = watch::channel(false);
+        let path = log_path(name).ok();
+        let lines_tail = Arc::clone(&tail);
+
+        tokio::spawn(async move {
+            let mut file = match &path {
+                Some(path) => open_log(path).await.ok(),
+                None => None,
+            };
+            let mut written = 0;
+            if let Some(f) = file.as_mut() {
+                written = f.metadata().await.map(|m| m.len()).unwrap_or(0);
+                let _ = f.write_all(format!("── {header} ──\n").as_bytes()).await;
+            }
+
+            // Read raw lines: stopping at invalid UTF-8 would leave the pipe
+            // undrained and block the server once it fills.
+            let mut reader = BufReader::new(stderr);
+            let mut buf = Vec::new();
+            loop {
+                buf.clear();
+                match reader.read_until(b'\n', &mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+                let line = String::from_utf8_lossy(&buf);
+                let line = line.trim_end_matches(['\n', '\r']).to_string();
+                {
+                    let mut tail = lines_tail.lock().unwrap();
+                    if tail.len() == TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line.clone());
+                }
+                if let (Some(f), Some(path)) = (file.as_mut(), &path) {
+                    let _ = f.write_all(format!("{line}\n").as_bytes()).await;
+                    written += line.len() as u64 + 1;
+                    if written >= MAX_LOG_BYTES {
+                        let _ = f.flush().await;
+                        file = open_log(path).await.ok();
+                        written = 0;
+                    }
+                }
+            }
+            if let Some(f) = file.as_mut() {
+                let _ = f.flush().await;
+            }
+            done_tx.send_replace(true);
+        });
+
+        Self { tail, done }
+    }
+
+    /// The last lines written to stderr. Gives the process a moment to finish
+    /// writing, since stderr usually trails the closed stdout of a crashing server.
+    pub async fn tail(&self) -> Vec<String> {
+        let mut done = self.done.clone();
+        let _ = tokio::time::timeout(Duration::from_millis(300), done.wait_for(|d| *d)).await;
+        self.tail.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Append one line to the log of server `name` (used for `notifications/message`).
+pub async fn append_line(name: &str, line: &str) -> Result<()> {
+    let mut file = open_log(&log_path(name)?).await?;
+    file.write_all(format!("{line}\n").as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Open `path` for appending, rotating it first if it is already too large.
+async fn open_log(path: &Path) -> Result<File> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let size = tokio::fs::metadata(path)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    if size >= MAX_LOG_BYTES {
+        for i in (1..ROTATED_LOGS).rev() {
+            let _ = tokio::fs::rename(rotated(path, i), rotated(path, i + 1)).await;
+        }
+        tokio::fs::rename(path, rotated(path, 1)).await?;
+    }
+    Ok(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?)
+}
+
+fn rotated(path: &Path, generation: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{generation}"));
+    PathBuf::from(name)
+}
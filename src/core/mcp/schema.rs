@@ -0,0 +1,288 @@
+//! Check tool arguments against a tool's `inputSchema` before calling the server.
+//!
+//! Only the parts of JSON Schema (2020-12) that tool schemas actually use are
+//! checked: `type`, `enum`, `const`, `required`, `properties`,
+//! `additionalProperties`, `items` and `prefixItems`. Anything else (`$ref`,
+//! `anyOf`, formats, ranges …) is accepted as-is and left to the server.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// One mismatch, located by a JSON Pointer into the arguments.
+#[derive(Debug, Serialize)]
+pub struct SchemaError {
+    pub pointer: String,
+    pub expected: String,
+    pub found: String,
+    pub message: String,
+}
+
+/// Every mismatch between `value` and `schema`; empty when the arguments are valid.
+pub fn validate(schema: &Value, value: &Value) -> Vec<SchemaError> {
+    let mut errors = Vec::new();
+    check(schema, value, "", &mut errors);
+    errors
+}
+
+fn check(schema: &Value, value: &Value, pointer: &str, errors: &mut Vec<SchemaError>) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            errors.push(error(pointer, "nothing", value, "no value is allowed here"));
+            return;
+        }
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            let expected = types.join(" or ");
+            let message = format!("expected {expected}, got {}", type_name(value));
+            errors.push(error(pointer, &expected, value, &message));
+            // Nested keywords would only repeat the same mistake.
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(value)
+    {
+        let expected = format!("one of {}", Value::Array(allowed.clone()));
+        let message = format!("{value} is not {expected}");
+        errors.push(error(pointer, &expected, value, &message));
+    }
+    if let Some(constant) = schema.get("const")
+        && constant != value
+    {
+        let message = format!("must be {constant}");
+        errors.push(error(pointer, &constant.to_string(), value, &message));
+    }
+
+    match value {
+        Value::Object(object) => check_object(schema, object, pointer, errors),
+        Value::Array(items) => check_array(schema, items, pointer, errors),
+        _ => {}
+    }
+}
+
+fn check_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    pointer: &str,
+    errors: &mut Vec<SchemaError>,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                let expected = properties
+                    .and_then(|p| p.get(key))
+                    .and_then(|s| s.get("type"))
+                    .map(describe_type)
+                    .unwrap_or_else(|| "a value".to_string());
+                errors.push(SchemaError {
+                    pointer: child(pointer, key),
+                    expected,
+                    found: "missing".to_string(),
+                    message: format!("required property '{key}' is missing"),
+                });
+            }
+        }
+    }
+
+    for (key, item) in object {
+        let item_pointer = child(pointer, key);
+        match properties.and_then(|p| p.get(key)) {
+            Some(item_schema) => check(item_schema, item, &item_pointer, errors),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    let known = properties
+                        .map(|p| p.keys().cloned().collect::<Vec<_>>().join(", "))
+                        .unwrap_or_default();
+                    let message = format!("unknown property '{key}' (allowed: {known})");
+                    errors.push(error(&item_pointer, "no such property", item, &message));
+                }
+                Some(extra) => check(extra, item, &item_pointer, errors),
+                None => {}
+            },
+        }
+    }
+}
+
+fn check_array(
+    schema: &Map<String, Value>,
+    items: &[Value],
+    pointer: &str,
+    errors: &mut Vec<SchemaError>,
+) {
+    let prefix = schema
+        .get("prefixItems")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for (i, item) in items.iter().enumerate() {
+        let item_schema = prefix.get(i).or_else(|| schema.get("items"));
+        if let Some(item_schema) = item_schema {
+            check(item_schema, item, &child(pointer, &i.to_string()), errors);
+        }
+    }
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        // 2020-12: any number with a zero fractional part is an integer.
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn describe_type(ty: &Value) -> String {
+    match ty {
+        Value::String(t) => t.clone(),
+        Value::Array(ts) => ts
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" or "),
+        _ => "a value".to_string(),
+    }
+}
+
+fn error(pointer: &str, expected: &str, found: &Value, message: &str) -> SchemaError {
+    SchemaError {
+        pointer: pointer.to_string(),
+        expected: expected.to_string(),
+        found: type_name(found).to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// Extend a JSON Pointer by one segment, escaping `~` and `/` (RFC 6901).
+fn child(pointer: &str, key: &str) -> String {
+    format!("{pointer}/{}", key.replace('~', "~0").replace('/', "~1"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pointers(schema: Value, value: Value) -> Vec<String> {
+        validate(&schema, &value)
+            .into_iter()
+            .map(|e| e.pointer)
+            .collect()
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let schema = json!({ "type": "object", "properties": { "n": { "type": "integer" } } });
+        let errors = validate(&schema, &json!({ "n": "3" }));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].pointer, "/n");
+        assert_eq!(errors[0].expected, "integer");
+        assert_eq!(errors[0].found, "string");
+        assert!(validate(&schema, &json!({ "n": 3.0 })).is_empty());
+        assert_eq!(pointers(json!({ "type": "object" }), json!([])), [""]);
+    }
+
+    #[test]
+    fn missing_required_property() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "path": { "type": "string" } },
+            "required": ["path", "mode"],
+        });
+        let errors = validate(&schema, &json!({}));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].pointer, "/path");
+        assert_eq!(errors[0].expected, "string");
+        assert_eq!(errors[0].found, "missing");
+        assert_eq!(errors[1].pointer, "/mode");
+        assert_eq!(errors[1].expected, "a value");
+    }
+
+    #[test]
+    fn enum_values() {
+        let schema = json!({ "properties": { "level": { "enum": ["low", "high"] } } });
+        assert!(validate(&schema, &json!({ "level": "high" })).is_empty());
+        let errors = validate(&schema, &json!({ "level": "mid" }));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].pointer, "/level");
+        assert_eq!(errors[0].expected, r#"one of ["low","high"]"#);
+    }
+
+    #[test]
+    fn nested_object_pointers() {
+        let schema = json!({
+            "properties": {
+                "opts": {
+                    "type": "object",
+                    "properties": { "depth": { "type": "integer" } },
+                    "required": ["name"],
+                    "additionalProperties": false,
+                }
+            }
+        });
+        assert_eq!(
+            pointers(schema, json!({ "opts": { "depth": "x", "extra": 1 } })),
+            ["/opts/name", "/opts/depth", "/opts/extra"]
+        );
+    }
+
+    #[test]
+    fn array_index_pointers() {
+        let schema = json!({
+            "properties": {
+                "items": { "type": "array", "items": { "type": "string" } },
+                "pair": { "prefixItems": [{ "type": "string" }, { "type": "integer" }] },
+            }
+        });
+        assert_eq!(
+            pointers(
+                schema,
+                json!({ "items": ["a", "b", 3], "pair": ["a", "b"] })
+            ),
+            ["/items/2", "/pair/1"]
+        );
+    }
+
+    #[test]
+    fn pointer_escaping() {
+        let schema = json!({
+            "properties": {
+                "a/b": { "type": "string" },
+                "c~d": { "type": "string" },
+            }
+        });
+        assert_eq!(
+            pointers(schema, json!({ "a/b": 1, "c~d": 2 })),
+            ["/a~1b", "/c~0d"]
+        );
+    }
+}
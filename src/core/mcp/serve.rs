@@ -0,0 +1,285 @@
+//! `mash mcp serve`: mash itself as a stdio MCP server, so editors and other
+//! agents can hand whole sub-tasks to it.
+//!
+//! Tools:
+//! - `run_agent`: run the agent loop on a prompt; returns the final answer and the task file
+//! - `bash`: run one command under the same `permissions` policy as the TUI
+//! - `list_tasks`: the task files of recent runs in this project
+//!
+//! Messages are newline-delimited JSON-RPC on stdin/stdout; stdout carries
+//! nothing else. Each call runs in its own task, so a long `run_agent` does
+//! not block `ping` or other calls.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{Mutex, mpsc};
+
+use crate::core::agent::{self, AgentEvent};
+use crate::core::api::{AnthropicClient, ContentBlock, Message, MessageContent};
+use crate::core::config::{ApiConfig, Settings};
+use crate::core::instructions::{self, InstructionFile};
+use crate::core::skills::{self, SkillInfo};
+use crate::core::{prompt, tasks, tools};
+
+/// Protocol version answered when the client asks for one we do not know.
+const PROTOCOL_VERSION: &str = "2025-06-18";
+const SUPPORTED_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+/// Task files returned by `list_tasks` when no `limit` is given.
+const DEFAULT_TASK_LIMIT: usize = 5;
+
+struct Server {
+    settings: Settings,
+    skills: Vec<SkillInfo>,
+    instruction_files: Vec<InstructionFile>,
+}
+
+/// Serve MCP on stdin/stdout until stdin closes.
+pub async fn run(settings: Settings) -> Result<()> {
+    // Fail at startup rather than on the first run_agent call.
+    ApiConfig::from_settings(&settings)?;
+    let server = Arc::new(Server {
+        settings,
+        skills: skills::scan_skills(),
+        instruction_files: instructions::discover_instructions(),
+    });
+
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<Value>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = out_rx.recv().await {
+            let line = message.to_string() + "\n";
+            if stdout.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+            let _ = stdout.flush().await;
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                let _ = out_tx.send(error_response(Value::Null, -32700, &e.to_string()));
+                continue;
+            }
+        };
+        // Notifications (no id) need no answer; `initialized` and `cancelled` included.
+        let Some(id) = message.get("id").cloned() else {
+            continue;
+        };
+        let method = message["method"].as_str().unwrap_or_default().to_string();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let server = Arc::clone(&server);
+        let out_tx = out_tx.clone();
+        tokio::spawn(async move {
+            let response = match server.handle(&method, params).await {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => error_response(id, code, &message),
+            };
+            let _ = out_tx.send(response);
+        });
+    }
+
+    drop(out_tx);
+    let _ = writer.await;
+    Ok(())
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+impl Server {
+    async fn handle(&self, method: &str, params: Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => {
+                let requested = params["protocolVersion"].as_str().unwrap_or_default();
+                let version = if SUPPORTED_VERSIONS.contains(&requested) {
+                    requested
+                } else {
+                    PROTOCOL_VERSION
+                };
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": { "name": "mash", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tool_list() })),
+            "tools/call" => {
+                let name = params["name"].as_str().unwrap_or_default();
+                let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+                Ok(self.call(name, arguments).await)
+            }
+            _ => Err((-32601, format!("method not found: {method}"))),
+        }
+    }
+
+    /// Failures are tool results with `isError`, so the caller's model sees them.
+    async fn call(&self, name: &str, arguments: Value) -> Value {
+        let outcome = match name {
+            "run_agent" => self.run_agent(&arguments).await,
+            "bash" => self.bash(arguments).await,
+            "list_tasks" => list_tasks(&arguments),
+            _ => Err(anyhow::anyhow!("unknown tool '{name}'")),
+        };
+        match outcome {
+            Ok(result) => result,
+            Err(e) => json!({
+                "content": [{ "type": "text", "text": e.to_string() }],
+                "isError": true,
+            }),
+        }
+    }
+
+    async fn run_agent(&self, arguments: &Value) -> Result<Value> {
+        let Some(input) = arguments["prompt"].as_str() else {
+            anyhow::bail!("'prompt' is required");
+        };
+        let task_file = tasks::init_task_file()?;
+        // No MCP section: a served mash does not start MCP servers of its own.
+        let system_prompt = format!(
+            "{}{}{}{}",
+            prompt::base_prompt(),
+            tasks::format_task_prompt(&task_file),
+            skills::format_skills_for_prompt(&self.skills),
+            instructions::format_instructions_for_prompt(&self.instruction_files),
+        );
+        let (fallbacks, _) = self.settings.fallback_configs();
+        let client = AnthropicClient::new(ApiConfig::from_settings(&self.settings)?, system_prompt)
+            .with_fallbacks(fallbacks)
+            .with_policy(self.settings.failover_policy());
+
+        let messages = Arc::new(Mutex::new(vec![Message {
+            role: "user".to_string(),
+            content: MessageContent::Text(input.to_string()),
+        }]));
+        // Nobody watches the live events of a served run.
+        let (tx, _) = mpsc::unbounded_channel::<AgentEvent>();
+        agent::run_agent_loop(
+            &client,
+            &tools::definitions(),
+            &messages,
+            tx,
+            &task_file,
+            &Arc::new(Mutex::new(Vec::new())),
+            &self.settings.permissions,
+        )
+        .await?;
+
+        let answer = final_answer(&messages.lock().await);
+        let tasks = tasks::read_task_content(&task_file).unwrap_or_default();
+        let mut text = answer.clone();
+        if tasks::read_task_summary(&task_file).is_some() {
+            text.push_str(&format!("\n\n{}\n{tasks}", task_file.display()));
+        }
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "structuredContent": {
+                "answer": answer,
+                "task_file": task_file,
+                "tasks": tasks,
+            },
+        }))
+    }
+
+    async fn bash(&self, arguments: Value) -> Result<Value> {
+        if !arguments["command"].is_string() {
+            anyhow::bail!("'command' is required");
+        }
+        let permissions = self.settings.permissions.clone();
+        let output =
+            tokio::task::spawn_blocking(move || tools::execute("bash", &arguments, &permissions))
+                .await??;
+        Ok(json!({ "content": [{ "type": "text", "text": output }] }))
+    }
+}
+
+fn list_tasks(arguments: &Value) -> Result<Value> {
+    let limit = arguments["limit"]
+        .as_u64()
+        .map_or(DEFAULT_TASK_LIMIT, |n| n as usize);
+    let files: Vec<Value> = tasks::recent_task_files(limit)?
+        .into_iter()
+        .map(|path| {
+            let (done, total) = tasks::read_task_summary(&path).unwrap_or((0, 0));
+            json!({
+                "path": path,
+                "done": done,
+                "total": total,
+                "content": tasks::read_task_content(&path).unwrap_or_default(),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "content": [{ "type": "text", "text": serde_json::to_string_pretty(&files)? }],
+        "structuredContent": { "tasks": files },
+    }))
+}
+
+/// Text of the last assistant message.
+fn final_answer(messages: &[Message]) -> String {
+    let Some(last) = messages.iter().rev().find(|m| m.role == "assistant") else {
+        return String::new();
+    };
+    match &last.content {
+        MessageContent::Text(text) => text.clone(),
+        MessageContent::Blocks(blocks) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn tool_list() -> Value {
+    json!([
+        {
+            "name": "run_agent",
+            "description": "Hand a whole task to the mash agent (bash-only coding agent) in the server's working directory. Returns its final answer and the task list it kept.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "prompt": { "type": "string", "description": "What the agent should do" }
+                },
+                "required": ["prompt"]
+            }
+        },
+        {
+            "name": "bash",
+            "description": "Run a bash command in the server's working directory, subject to mash's permissions.allow/deny rules. Returns stdout and stderr.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "command": { "type": "string", "description": "The command to run" }
+                },
+                "required": ["command"]
+            }
+        },
+        {
+            "name": "list_tasks",
+            "description": "Task lists of the most recent mash runs in this project, newest first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "limit": { "type": "integer", "description": "How many task files to return (default 5)" }
+                }
+            }
+        }
+    ])
+}
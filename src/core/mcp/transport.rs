@@ -0,0 +1,552 @@
+//! MCP transports: stdio child processes, streamable HTTP, and legacy SSE.
+//!
+//! Every transport sends JSON-RPC messages through [`Transport::send`] and delivers
+//! everything the server sends back on the `incoming` channel of its [`Connection`],
+//! so the client above does not care where a server runs.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Response, StatusCode, Url};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{Mutex, mpsc, oneshot};
+
+use super::McpServerConfig;
+use super::logs::StderrLog;
+use crate::core::config::interpolate_env;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+const SESSION_HEADER: &str = "mcp-session-id";
+
+/// Outgoing half of a connection to one MCP server.
+pub trait Transport: Send + Sync {
+    /// Send one JSON-RPC message (request, response or notification).
+    fn send(&self, message: Value) -> BoxFuture<'_, Result<()>>;
+}
+
+/// A live connection: the transport plus every message received from the server.
+/// `incoming` closes when the server goes away: its process exits, its stream
+/// ends, or a request to it fails at the HTTP level.
+pub struct Connection {
+    pub transport: Box<dyn Transport>,
+    pub incoming: mpsc::UnboundedReceiver<Value>,
+    /// stderr of a stdio server, mirrored to its log file.
+    pub stderr: Option<StderrLog>,
+}
+
+/// How a server is reached, from the `type` field of its config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    Stdio,
+    Http,
+    Sse,
+}
+
+impl TransportKind {
+    pub fn of(config: &McpServerConfig) -> Result<Self> {
+        match config.transport.as_deref() {
+            None if config.url.is_some() => Ok(Self::Http),
+            None | Some("stdio") => Ok(Self::Stdio),
+            Some("http") | Some("streamable-http") => Ok(Self::Http),
+            Some("sse") => Ok(Self::Sse),
+            Some(other) => bail!("unknown MCP transport type '{other}'"),
+        }
+    }
+}
+
+/// Open a connection to `config` using the transport it asks for.
+pub async fn connect(name: &str, config: &McpServerConfig) -> Result<Connection> {
+    match TransportKind::of(config)? {
+        TransportKind::Stdio => StdioTransport::spawn(name, config),
+        TransportKind::Http => HttpTransport::open(name, config),
+        TransportKind::Sse => SseTransport::open(name, config).await,
+    }
+}
+
+// ── stdio ───────────────────────────────────────────────────────
+
+/// Newline-delimited JSON over a child process's stdin/stdout.
+struct StdioTransport {
+    _child: Child,
+    stdin: Mutex<ChildStdin>,
+}
+
+impl StdioTransport {
+    fn spawn(name: &str, config: &McpServerConfig) -> Result<Connection> {
+        if config.command.is_empty() {
+            bail!("MCP server '{name}' has no command");
+        }
+        let mut cmd = Command::new(&config.command);
+        cmd.args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        for (k, v) in &config.env {
+            let value =
+                interpolate_env(v).with_context(|| format!("in env.{k} of MCP server '{name}'"))?;
+            cmd.env(k, value);
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("failed to start '{}': {e}", config.command))?;
+        let stdin = child.stdin.take().expect("stdin not captured");
+        let stdout = child.stdout.take().expect("stdout not captured");
+        let stderr = child.stderr.take().expect("stderr not captured");
+        let header = match child.id() {
+            Some(pid) => format!("{} (pid {pid})", config.describe()),
+            None => config.describe(),
+        };
+        let stderr = StderrLog::capture(name, stderr, header);
+
+        let (tx, incoming) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // Servers sometimes log to stdout; skip anything that is not JSON.
+                if let Ok(msg) = serde_json::from_str::<Value>(&line)
+                    && tx.send(msg).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(Connection {
+            transport: Box::new(Self {
+                _child: child,
+                stdin: Mutex::new(stdin),
+            }),
+            incoming,
+            stderr: Some(stderr),
+        })
+    }
+}
+
+impl Transport for StdioTransport {
+    fn send(&self, message: Value) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let mut line = serde_json::to_string(&message)?;
+            line.push('\n');
+            let mut stdin = self.stdin.lock().await;
+            stdin.write_all(line.as_bytes()).await?;
+            stdin.flush().await?;
+            Ok(())
+        })
+    }
+}
+
+// ── Streamable HTTP ─────────────────────────────────────────────
+
+/// Streamable HTTP transport: every message is a POST; the server answers with
+/// plain JSON, an SSE stream, or 202 for notifications. The `Mcp-Session-Id`
+/// returned by `initialize` is sent back on every later request.
+struct HttpTransport {
+    name: String,
+    client: Client,
+    url: Url,
+    headers: HeaderMap,
+    session_id: std::sync::Mutex<Option<String>>,
+    inbox: Inbox,
+}
+
+impl HttpTransport {
+    fn open(name: &str, config: &McpServerConfig) -> Result<Connection> {
+        let url = server_url(name, config)?;
+        let headers = build_headers(name, config)?;
+        let (inbox, incoming) = Inbox::new();
+        Ok(Connection {
+            transport: Box::new(Self {
+                name: name.to_string(),
+                client: Client::new(),
+                url,
+                headers,
+                session_id: std::sync::Mutex::new(None),
+                inbox,
+            }),
+            incoming,
+            stderr: None,
+        })
+    }
+}
+
+impl Transport for HttpTransport {
+    fn send(&self, message: Value) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let mut req = self
+                .client
+                .post(self.url.clone())
+                .headers(self.headers.clone())
+                .header(ACCEPT, "application/json, text/event-stream")
+                .json(&message);
+            let session = self.session_id.lock().unwrap().clone();
+            if let Some(session) = session {
+                req = req.header(SESSION_HEADER, session);
+            }
+
+            let resp = match req.send().await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    self.inbox.close();
+                    return Err(e.into());
+                }
+            };
+            if let Some(session) = resp
+                .headers()
+                .get(SESSION_HEADER)
+                .and_then(|v| v.to_str().ok())
+            {
+                *self.session_id.lock().unwrap() = Some(session.to_string());
+            }
+
+            let status = resp.status();
+            if status == StatusCode::ACCEPTED {
+                return Ok(());
+            }
+            if !status.is_success() {
+                // An unknown session or a failing server ends the connection; other
+                // client errors only concern this message.
+                if status == StatusCode::NOT_FOUND || status.is_server_error() {
+                    self.inbox.close();
+                }
+                let body = resp.text().await.unwrap_or_default();
+                bail!("MCP server '{}' HTTP error ({status}): {body}", self.name);
+            }
+
+            if is_event_stream(&resp) {
+                // The stream stays open until the server has sent the response, so
+                // read it in the background and let `send` return right away.
+                let inbox = self.inbox.clone();
+                tokio::spawn(async move {
+                    if forward_sse(resp, &inbox, None).await.is_err() {
+                        inbox.close();
+                    }
+                });
+                return Ok(());
+            }
+
+            let body = match resp.text().await {
+                Ok(body) => body,
+                Err(e) => {
+                    self.inbox.close();
+                    return Err(e.into());
+                }
+            };
+            if body.trim().is_empty() {
+                return Ok(());
+            }
+            forward_json(serde_json::from_str(&body)?, &self.inbox);
+            Ok(())
+        })
+    }
+}
+
+// ── Legacy SSE ──────────────────────────────────────────────────
+
+/// Legacy HTTP+SSE transport: a long-lived GET stream carries server messages and
+/// first announces, in an `endpoint` event, the URL that client messages are POSTed to.
+struct SseTransport {
+    name: String,
+    client: Client,
+    endpoint: Url,
+    headers: HeaderMap,
+    inbox: Inbox,
+}
+
+impl SseTransport {
+    async fn open(name: &str, config: &McpServerConfig) -> Result<Connection> {
+        let url = server_url(name, config)?;
+        let headers = build_headers(name, config)?;
+        let client = Client::new();
+
+        let request = client
+            .get(url.clone())
+            .headers(headers.clone())
+            .header(ACCEPT, "text/event-stream")
+            .send();
+        let Ok(resp) = tokio::time::timeout(config.request_timeout(), request).await else {
+            bail!(
+                "MCP server '{name}' did not open its SSE stream within {}s",
+                config.request_timeout().as_secs()
+            );
+        };
+        let resp = resp?;
+        if !resp.status().is_success() {
+            bail!("MCP server '{name}' SSE stream error ({})", resp.status());
+        }
+
+        let (inbox, incoming) = Inbox::new();
+        let (endpoint_tx, endpoint_rx) = oneshot::channel();
+        let reader = inbox.clone();
+        tokio::spawn(async move {
+            // The GET stream carries every response; when it ends, so does the connection.
+            let _ = forward_sse(resp, &reader, Some(endpoint_tx)).await;
+            reader.close();
+        });
+
+        let endpoint = match tokio::time::timeout(config.request_timeout(), endpoint_rx).await {
+            Ok(Ok(endpoint)) => endpoint,
+            Ok(Err(_)) => {
+                bail!("MCP server '{name}' closed SSE stream before sending its endpoint")
+            }
+            Err(_) => bail!(
+                "MCP server '{name}' sent no endpoint within {}s",
+                config.request_timeout().as_secs()
+            ),
+        };
+        let endpoint = url
+            .join(&endpoint)
+            .with_context(|| format!("MCP server '{name}' sent invalid endpoint '{endpoint}'"))?;
+        // The configured headers (often credentials) are sent to the endpoint, so it
+        // must stay on the server that was configured.
+        if endpoint.origin() != url.origin() {
+            bail!("MCP server '{name}' sent endpoint '{endpoint}' on another origin than '{url}'");
+        }
+
+        Ok(Connection {
+            transport: Box::new(Self {
+                name: name.to_string(),
+                client,
+                endpoint,
+                headers,
+                inbox,
+            }),
+            incoming,
+            stderr: None,
+        })
+    }
+}
+
+impl Transport for SseTransport {
+    fn send(&self, message: Value) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let resp = self
+                .client
+                .post(self.endpoint.clone())
+                .headers(self.headers.clone())
+                .json(&message)
+                .send()
+                .await
+                .inspect_err(|_| self.inbox.close())?;
+            if !resp.status().is_success() {
+                let status = resp.status();
+                let body = resp.text().await.unwrap_or_default();
+                bail!("MCP server '{}' HTTP error ({status}): {body}", self.name);
+            }
+            Ok(())
+        })
+    }
+}
+
+// ── Helpers ─────────────────────────────────────────────────────
+
+/// Sending half of `incoming` for the HTTP transports, shared by the transport and
+/// its stream readers. It is the only sender, so [`Inbox::close`] closes `incoming`
+/// even while other readers are still running.
+#[derive(Clone)]
+struct Inbox(Arc<std::sync::Mutex<Option<mpsc::UnboundedSender<Value>>>>);
+
+impl Inbox {
+    fn new() -> (Self, mpsc::UnboundedReceiver<Value>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self(Arc::new(std::sync::Mutex::new(Some(tx)))), rx)
+    }
+
+    /// Deliver `msg`; false once the connection is closed.
+    fn send(&self, msg: Value) -> bool {
+        match self.0.lock().unwrap().as_ref() {
+            Some(tx) => tx.send(msg).is_ok(),
+            None => false,
+        }
+    }
+
+    fn close(&self) {
+        self.0.lock().unwrap().take();
+    }
+}
+
+fn server_url(name: &str, config: &McpServerConfig) -> Result<Url> {
+    let Some(url) = &config.url else {
+        bail!("MCP server '{name}' has no url");
+    };
+    let url = interpolate_env(url).with_context(|| format!("in url of MCP server '{name}'"))?;
+    Url::parse(&url).with_context(|| format!("MCP server '{name}' has invalid url '{url}'"))
+}
+
+fn build_headers(name: &str, config: &McpServerConfig) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (k, v) in &config.headers {
+        let value =
+            interpolate_env(v).with_context(|| format!("in headers.{k} of MCP server '{name}'"))?;
+        headers.insert(
+            HeaderName::from_bytes(k.as_bytes())
+                .with_context(|| format!("invalid header name '{k}'"))?,
+            HeaderValue::from_str(&value)
+                .with_context(|| format!("invalid value for header '{k}'"))?,
+        );
+    }
+    Ok(headers)
+}
+
+fn is_event_stream(resp: &Response) -> bool {
+    resp.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/event-stream"))
+}
+
+/// Forward a JSON body, which may be a single message or a JSON-RPC batch.
+fn forward_json(body: Value, inbox: &Inbox) {
+    match body {
+        Value::Array(batch) => {
+            for msg in batch {
+                inbox.send(msg);
+            }
+        }
+        msg => {
+            inbox.send(msg);
+        }
+    }
+}
+
+/// Read an SSE response to the end, forwarding `message` events as JSON. The
+/// first `endpoint` event is handed to `endpoint_tx` when one is given.
+async fn forward_sse(
+    mut resp: Response,
+    inbox: &Inbox,
+    mut endpoint_tx: Option<oneshot::Sender<String>>,
+) -> Result<()> {
+    let mut parser = SseParser::default();
+    while let Some(chunk) = resp.chunk().await? {
+        for event in parser.push(&chunk) {
+            match event.event.as_str() {
+                "endpoint" => {
+                    if let Some(sender) = endpoint_tx.take() {
+                        let _ = sender.send(event.data);
+                    }
+                }
+                "" | "message" => {
+                    if let Ok(msg) = serde_json::from_str::<Value>(&event.data) {
+                        forward_json(msg, inbox);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One server-sent event; `event` is empty when the stream did not name it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SseEvent {
+    event: String,
+    data: String,
+}
+
+/// Incremental `text/event-stream` parser fed with arbitrary byte chunks. Bytes
+/// are buffered until a whole line is in, so a character split across chunks
+/// is decoded intact.
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let bytes: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&bytes);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: std::mem::take(&mut self.event),
+                        data: self.data.join("\n"),
+                    });
+                    self.data.clear();
+                }
+                self.event.clear();
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = value.to_string(),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event: &str, data: &str) -> SseEvent {
+        SseEvent {
+            event: event.to_string(),
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn events_split_across_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"event: endpoint\nda").is_empty());
+        assert!(parser.push(b"ta: /messages?id=1\n").is_empty());
+        assert_eq!(
+            parser.push(b"\ndata: {}\n\n"),
+            [event("endpoint", "/messages?id=1"), event("", "{}")]
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let mut parser = SseParser::default();
+        assert_eq!(
+            parser.push(b"event: message\r\ndata: 1\r\n\r\n"),
+            [event("message", "1")]
+        );
+    }
+
+    #[test]
+    fn multi_line_data_and_comments() {
+        let mut parser = SseParser::default();
+        assert_eq!(
+            parser.push(b": keep-alive\ndata: {\"a\":\ndata:1}\n\n"),
+            [event("", "{\"a\":\n1}")]
+        );
+    }
+
+    #[test]
+    fn utf8_split_inside_a_character() {
+        let text = "data: héllo 世界\n\n".as_bytes();
+        // Split inside the three-byte '世'.
+        let split = text.iter().position(|&b| b == 0xe4).unwrap() + 1;
+        let mut parser = SseParser::default();
+        assert!(parser.push(&text[..split]).is_empty());
+        assert_eq!(parser.push(&text[split..]), [event("", "héllo 世界")]);
+    }
+}
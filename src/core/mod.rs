@@ -1,7 +1,9 @@
 pub mod agent;
 pub mod api;
 pub mod config;
+pub mod instructions;
 pub mod mcp;
+pub mod prompt;
 pub mod skills;
 pub mod tasks;
 pub mod tools;
@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::core::config::{Settings, project_dir, project_is_trusted};
+use crate::core::instructions::{self, InstructionFile};
+use crate::core::mcp::{self, BridgeAddr, McpManager};
+use crate::core::skills::{self, SkillInfo};
+use crate::core::tasks;
+
+/// A static system prompt module, see `src/core/prompt/*.md`.
+pub struct PromptModule {
+    pub name: &'static str,
+    pub builtin: &'static str,
+}
+
+/// Built-in modules in assembly order.
+pub const MODULES: [PromptModule; 5] = [
+    PromptModule {
+        name: "identity",
+        builtin: include_str!("prompt/identity.md"),
+    },
+    PromptModule {
+        name: "tools_general",
+        builtin: include_str!("prompt/tools_general.md"),
+    },
+    PromptModule {
+        name: "tools_specialized",
+        builtin: include_str!("prompt/tools_specialized.md"),
+    },
+    PromptModule {
+        name: "work_style",
+        builtin: include_str!("prompt/work_style.md"),
+    },
+    PromptModule {
+        name: "response_format",
+        builtin: include_str!("prompt/response_format.md"),
+    },
+];
+
+/// Directories searched for module overrides, lowest precedence first:
+/// `~/.mash/prompt/`, then `<project>/.mash/prompt/` if the project is in
+/// `trusted_projects` (a cloned repository could otherwise replace the identity).
+pub fn override_dirs() -> Vec<PathBuf> {
+    let mut dirs_in_order = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        dirs_in_order.push(home.join(".mash").join("prompt"));
+    }
+    if let Some(project) = project_dir()
+        && project_is_trusted()
+    {
+        let dir = project.join(".mash").join("prompt");
+        if !dirs_in_order.contains(&dir) {
+            dirs_in_order.push(dir);
+        }
+    }
+    dirs_in_order
+}
+
+/// Resolve one module against the override directories.
+///
+/// `<name>.md` replaces the module (the project file wins over the user file);
+/// `<name>.append.md` is appended after it, user file first.
+pub fn resolve_module(module: &PromptModule, dirs: &[PathBuf]) -> String {
+    let mut body = module.builtin.to_string();
+    let mut appends = Vec::new();
+    for dir in dirs {
+        if let Ok(content) = fs::read_to_string(dir.join(format!("{}.md", module.name))) {
+            body = content;
+        }
+        if let Ok(content) = fs::read_to_string(dir.join(format!("{}.append.md", module.name))) {
+            appends.push(content);
+        }
+    }
+    for extra in appends {
+        body = format!("{}\n\n{}", body.trim_end(), extra);
+    }
+    body
+}
+
+/// The static part of the system prompt with user/project overrides applied.
+pub fn base_prompt() -> String {
+    let dirs = override_dirs();
+    MODULES
+        .iter()
+        .map(|m| resolve_module(m, &dirs))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Compose the full system prompt: static modules followed by the runtime
+/// MCP, task, skills and project instruction sections.
+pub fn compose_system_prompt(
+    mcp: &McpManager,
+    mcp_bridge: &BridgeAddr,
+    settings: &Settings,
+    task_file: &Path,
+    skills: &[SkillInfo],
+    instruction_files: &[InstructionFile],
+) -> String {
+    prompt_sections(
+        mcp,
+        mcp_bridge,
+        settings,
+        task_file,
+        skills,
+        instruction_files,
+    )
+    .into_iter()
+    .map(|(_, section)| section)
+    .collect()
+}
+
+/// A part of the system prompt, as listed by `mash prompt show`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptSection {
+    Base,
+    Mcp,
+    Tasks,
+    Skills,
+    Instructions,
+}
+
+impl PromptSection {
+    pub fn label(self) -> &'static str {
+        match self {
+            PromptSection::Base => "base",
+            PromptSection::Mcp => "mcp",
+            PromptSection::Tasks => "tasks",
+            PromptSection::Skills => "skills",
+            PromptSection::Instructions => "instructions",
+        }
+    }
+}
+
+/// The parts of [`compose_system_prompt`] in order.
+pub fn prompt_sections(
+    mcp: &McpManager,
+    mcp_bridge: &BridgeAddr,
+    settings: &Settings,
+    task_file: &Path,
+    skills: &[SkillInfo],
+    instruction_files: &[InstructionFile],
+) -> Vec<(PromptSection, String)> {
+    vec![
+        (PromptSection::Base, base_prompt()),
+        (
+            PromptSection::Mcp,
+            mcp::format_mcp_tools_for_prompt(mcp, mcp_bridge, settings.mcp_compact_threshold()),
+        ),
+        (PromptSection::Tasks, tasks::format_task_prompt(task_file)),
+        (
+            PromptSection::Skills,
+            skills::format_skills_for_prompt(skills),
+        ),
+        (
+            PromptSection::Instructions,
+            instructions::format_instructions_for_prompt(instruction_files),
+        ),
+    ]
+}
+
+/// Rough token count without a tokenizer: about four ASCII characters per
+/// token, and one token per other character (CJK text).
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(char::is_ascii).count();
+    let other = text.chars().count() - ascii;
+    ascii.div_ceil(4) + other
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_tokens_counts_ascii_by_four() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[test]
+    fn estimate_tokens_counts_other_chars_as_one() {
+        assert_eq!(estimate_tokens("你好"), 2);
+        assert_eq!(estimate_tokens("ab你好"), 3);
+        assert_eq!(estimate_tokens("é"), 1);
+    }
+}
@@ -12,16 +12,25 @@ pub struct SkillInfo {
 
 /// Scan `~/.claude/skills/` for skill.md files and extract name + description from YAML frontmatter.
 pub fn scan_skills() -> Vec<SkillInfo> {
+    scan_skills_with_problems().0
+}
+
+/// Like [`scan_skills`], but also returns a description of every entry that was skipped.
+pub fn scan_skills_with_problems() -> (Vec<SkillInfo>, Vec<String>) {
     let Some(home) = dirs::home_dir() else {
-        return Vec::new();
+        return (
+            Vec::new(),
+            vec!["could not determine home directory".to_string()],
+        );
     };
     let skills_dir = home.join(".claude").join("skills");
     let entries = match fs::read_dir(&skills_dir) {
         Ok(e) => e,
-        Err(_) => return Vec::new(),
+        Err(_) => return (Vec::new(), Vec::new()),
     };
 
     let mut skills = Vec::new();
+    let mut problems = Vec::new();
     for entry in entries.flatten() {
         let path = entry.path();
         // Skip hidden files like .DS_Store
@@ -35,19 +44,33 @@ pub fn scan_skills() -> Vec<SkillInfo> {
 
         let skill_md = find_skill_md(&path);
         let Some(skill_md) = skill_md else {
+            problems.push(format!("{}: no skill.md or SKILL.md", path.display()));
             continue;
         };
         let Ok(content) = fs::read_to_string(&skill_md) else {
+            problems.push(format!("{}: unreadable", skill_md.display()));
             continue;
         };
-        if let Some(mut info) = parse_frontmatter(&content) {
-            info.path = skill_md.to_string_lossy().to_string();
-            skills.push(info);
+        match parse_frontmatter(&content) {
+            Some(mut info) => {
+                if info.description.is_empty() {
+                    problems.push(format!(
+                        "{}: frontmatter has no description",
+                        skill_md.display()
+                    ));
+                }
+                info.path = skill_md.to_string_lossy().to_string();
+                skills.push(info);
+            }
+            None => problems.push(format!(
+                "{}: missing `---` frontmatter or `name:` field",
+                skill_md.display()
+            )),
         }
     }
 
     skills.sort_by(|a, b| a.name.cmp(&b.name));
-    skills
+    (skills, problems)
 }
 
 /// Find skill.md or SKILL.md in the given path (could be a directory or symlink to one).
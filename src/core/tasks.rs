@@ -1,4 +1,5 @@
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -55,18 +56,89 @@ fn tasks_dir() -> Result<PathBuf> {
     Ok(dir)
 }
 
-/// Initialize a new task file for this session, returning its path.
-pub fn init_task_file() -> Result<PathBuf> {
-    let dir = tasks_dir()?;
-    let ts = SystemTime::now()
+/// Path of a new session task file, without creating it.
+pub fn new_task_file_path() -> Result<PathBuf> {
+    Ok(tasks_dir()?.join(task_file_name(&project_name(), now_secs(), 0)))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|d| d.as_secs())
-        .unwrap_or(0);
-    let name = format!("{}_{}.md", project_name(), ts);
-    let path = dir.join(name);
-    // 仅写标题，不写占位符；模型在首条回复里用 TaskCreate 创建多步任务时才写入清单
-    fs::write(&path, format!("# Tasks — {}\n\n", project_name()))?;
-    Ok(path)
+        .unwrap_or(0)
+}
+
+/// `<project>_<ts>.md`, or `<project>_<ts>-<seq>.md` for the second and later
+/// file started within the same second.
+fn task_file_name(project: &str, ts: u64, seq: u32) -> String {
+    if seq == 0 {
+        format!("{project}_{ts}.md")
+    } else {
+        format!("{project}_{ts}-{seq}.md")
+    }
+}
+
+/// `(ts, seq)` of a task file name belonging to `project` exactly, so that
+/// `mash` does not pick up the files of `mash_cli`.
+fn task_file_stamp(name: &str, project: &str) -> Option<(u64, u32)> {
+    let stem = name.strip_suffix(".md")?;
+    let (owner, stamp) = stem.rsplit_once('_')?;
+    if owner != project {
+        return None;
+    }
+    let (ts, seq) = match stamp.split_once('-') {
+        Some((ts, seq)) => (ts, seq),
+        None => (stamp, "0"),
+    };
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(ts) || !digits(seq) {
+        return None;
+    }
+    Some((ts.parse().ok()?, seq.parse().ok()?))
+}
+
+/// Task files of the current project, newest first.
+pub fn recent_task_files(limit: usize) -> Result<Vec<PathBuf>> {
+    let project = project_name();
+    let mut files: Vec<((u64, u32), PathBuf)> = fs::read_dir(tasks_dir()?)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| {
+            let stamp = task_file_stamp(path.file_name()?.to_str()?, &project)?;
+            Some((stamp, path))
+        })
+        .collect();
+    files.sort_by_key(|(stamp, _)| std::cmp::Reverse(*stamp));
+    Ok(files
+        .into_iter()
+        .take(limit)
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// Initialize a new task file for this session, returning its path. The file is
+/// created exclusively, so sessions started in the same second (concurrent
+/// `run_agent` calls of `mash serve`) each get their own.
+pub fn init_task_file() -> Result<PathBuf> {
+    let dir = tasks_dir()?;
+    let project = project_name();
+    let ts = now_secs();
+    let mut seq = 0;
+    loop {
+        let path = dir.join(task_file_name(&project, ts, seq));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(mut file) => {
+                // 仅写标题，不写占位符；模型在首条回复里用 TaskCreate 创建多步任务时才写入清单
+                file.write_all(format!("# Tasks — {project}\n\n").as_bytes())?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => seq += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
 }
 
 /// Write the current task list to the task file.
@@ -101,3 +173,38 @@ pub fn read_task_summary(path: &Path) -> Option<(usize, usize)> {
         .count();
     Some((done, total))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamp_requires_the_exact_project() {
+        assert_eq!(
+            task_file_stamp("mash_1700000000.md", "mash"),
+            Some((1700000000, 0))
+        );
+        assert_eq!(
+            task_file_stamp("mash_1700000000-2.md", "mash"),
+            Some((1700000000, 2))
+        );
+        assert_eq!(task_file_stamp("mash_cli_1700000000.md", "mash"), None);
+        assert_eq!(
+            task_file_stamp("mash_cli_1700000000.md", "mash_cli"),
+            Some((1700000000, 0))
+        );
+        assert_eq!(task_file_stamp("mash_notes.md", "mash"), None);
+        assert_eq!(task_file_stamp("mash_17-.md", "mash"), None);
+        assert_eq!(task_file_stamp("mash_1700000000.txt", "mash"), None);
+    }
+
+    #[test]
+    fn names_round_trip_and_sort_numerically() {
+        let mut stamps: Vec<_> = [(999, 0), (1000, 0), (1000, 10), (1000, 2)]
+            .iter()
+            .map(|&(ts, seq)| task_file_stamp(&task_file_name("p", ts, seq), "p").unwrap())
+            .collect();
+        stamps.sort();
+        assert_eq!(stamps, vec![(999, 0), (1000, 0), (1000, 2), (1000, 10)]);
+    }
+}
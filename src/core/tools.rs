@@ -1,7 +1,9 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use serde_json::{Value, json};
 use std::process::Command;
 
+use crate::core::config::Permissions;
+
 pub fn definitions() -> Vec<Value> {
     vec![json!({
         "name": "bash",
@@ -23,17 +25,30 @@ pub fn definitions() -> Vec<Value> {
     })]
 }
 
-pub fn execute(name: &str, input: &Value) -> Result<String> {
+pub fn execute(name: &str, input: &Value, permissions: &Permissions) -> Result<String> {
     match name {
-        "bash" => exec_bash(input),
+        "bash" => exec_bash(input, permissions),
         _ => Ok(format!("Unknown tool: {name}")),
     }
 }
 
-fn exec_bash(input: &Value) -> Result<String> {
+fn exec_bash(input: &Value, permissions: &Permissions) -> Result<String> {
     let command = input["command"].as_str().unwrap_or_default();
 
-    let output = Command::new("bash").arg("-c").arg(command).output()?;
+    if let Some(rule) = permissions.denied_by(command) {
+        bail!("command blocked by permissions.deny rule '{rule}'");
+    }
+
+    let mut cmd = Command::new("bash");
+    cmd.arg("-c").arg(command);
+    // Lets the model's curl and `mash mcp invoke` calls reach the MCP bridge.
+    if let Some(token) = crate::core::mcp::session_token() {
+        cmd.env(crate::core::mcp::MCP_TOKEN_ENV, token);
+    }
+    if let Some(bridge) = crate::core::mcp::session_bridge() {
+        cmd.env(crate::core::mcp::MCP_BRIDGE_ENV, bridge.to_string());
+    }
+    let output = cmd.output()?;
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
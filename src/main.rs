@@ -1,11 +1,27 @@
-use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
 use clap::{Parser, Subcommand};
-use mash::core::mcp::McpManager;
+use mash::core::api::{AnthropicClient, FailoverPolicy, Message, MessageContent};
+use mash::core::config::{ApiConfig, CliOverrides, Settings, settings_paths};
+use mash::core::mcp::config_file::{self, ImportSource, McpScope};
+use mash::core::mcp::{self, McpManager};
+use mash::core::prompt::PromptSection;
+use mash::core::{instructions, prompt, skills, tasks};
+use serde_json::{Value, json};
 use tokio::time::{Duration, timeout};
 
 #[derive(Parser)]
 #[command(name = "mash", version, about = "A minimal Claude agent")]
 struct Cli {
+    /// Model provider name from settings.json (overrides every config file)
+    #[arg(long, global = true)]
+    provider: Option<String>,
+    /// Model name (overrides every config file)
+    #[arg(long, global = true)]
+    model: Option<String>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -17,6 +33,18 @@ enum Commands {
         #[command(subcommand)]
         action: McpAction,
     },
+    /// Configuration inspection
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Check settings, provider, MCP servers and skills for problems
+    Doctor,
+    /// System prompt inspection
+    Prompt {
+        #[command(subcommand)]
+        action: PromptAction,
+    },
 }
 
 #[derive(Subcommand)]
@@ -28,42 +56,226 @@ enum McpAction {
         /// MCP server name
         name: String,
     },
+    /// Show the captured stderr of a stdio MCP server
+    Logs {
+        /// MCP server name
+        name: String,
+        /// Keep printing new output as it is written
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Add a server: `mash mcp add <name> -- <command> [args]` or `--url <url>`
+    Add {
+        /// MCP server name
+        name: String,
+        /// Command line of a stdio server, after `--`
+        #[arg(last = true)]
+        command: Vec<String>,
+        /// Endpoint of an HTTP or SSE server
+        #[arg(long, conflicts_with = "command")]
+        url: Option<String>,
+        /// Transport for --url: http (default) or sse
+        #[arg(long = "type", requires = "url")]
+        transport: Option<String>,
+        /// Environment variable for a stdio server (repeatable)
+        #[arg(short, long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// HTTP header for --url servers (repeatable)
+        #[arg(long = "header", value_name = "KEY=VALUE", requires = "url")]
+        headers: Vec<String>,
+        /// Where to save: user (~/.mash/mcp.json) or project (<project>/.mash/mcp.json)
+        #[arg(long, default_value = "user")]
+        scope: McpScope,
+    },
+    /// Remove a server from mcp.json
+    Remove {
+        /// MCP server name
+        name: String,
+        /// user or project; needed only when both define the server
+        #[arg(long)]
+        scope: Option<McpScope>,
+    },
+    /// Enable a disabled server
+    Enable {
+        /// MCP server name
+        name: String,
+        /// user or project; needed only when both define the server
+        #[arg(long)]
+        scope: Option<McpScope>,
+    },
+    /// Disable a server without removing it
+    Disable {
+        /// MCP server name
+        name: String,
+        /// user or project; needed only when both define the server
+        #[arg(long)]
+        scope: Option<McpScope>,
+    },
+    /// Import servers from claude-desktop or claude-code
+    Import {
+        /// claude-desktop or claude-code
+        source: ImportSource,
+        /// Read this config file instead of the default location
+        #[arg(long)]
+        from: Option<PathBuf>,
+        /// Where to save: user or project
+        #[arg(long, default_value = "user")]
+        scope: McpScope,
+        /// Replace servers that already exist
+        #[arg(long)]
+        force: bool,
+    },
+    /// Run mash as a stdio MCP server (run_agent, bash, list_tasks)
+    Serve,
+    /// Call a tool directly, outside a session
+    Call {
+        /// MCP server name
+        server: String,
+        /// Tool name
+        tool: String,
+        /// Tool arguments as a JSON object
+        #[arg(long, default_value = "{}")]
+        json: String,
+        /// Confirm a tool listed in approveTools (interactive terminal only)
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Call a tool through the running session's bridge (from bash inside a session)
+    Invoke {
+        /// MCP server name
+        server: String,
+        /// Tool name
+        tool: String,
+        /// `key=value` (string) or `key:=<json>`; without any, a JSON object is read from stdin
+        #[arg(value_name = "ARG")]
+        args: Vec<String>,
+        /// Print the tool's structured result as JSON
+        #[arg(long)]
+        structured: bool,
+        /// Print the tool's input schema instead of calling it
+        #[arg(long, conflicts_with_all = ["args", "structured"])]
+        schema: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the effective settings after merging all layers
+    Show {
+        /// Show which file (or env/cli) each value came from
+        #[arg(long)]
+        origin: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PromptAction {
+    /// Print the fully composed system prompt (modules, MCP, tasks, skills, instructions),
+    /// with a size report per section on stderr
+    Show,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let overrides = CliOverrides {
+        model_provider: cli.provider,
+        model: cli.model,
+    };
 
-    match cli.command {
-        None => mash::tui::run().await,
+    let result = match cli.command {
+        None => mash::tui::run(overrides).await,
         Some(Commands::Mcp { action }) => match action {
             McpAction::List => cmd_mcp_list().await,
             McpAction::Tools { name } => cmd_mcp_tools(&name).await,
+            McpAction::Logs { name, follow } => cmd_mcp_logs(&name, follow).await,
+            McpAction::Add {
+                name,
+                command,
+                url,
+                transport,
+                env,
+                headers,
+                scope,
+            } => cmd_mcp_add(&name, command, url, transport, &env, &headers, scope),
+            McpAction::Remove { name, scope } => {
+                let path = config_file::remove_server(scope, &name)?;
+                println!("Removed MCP server '{name}' from {}", path.display());
+                Ok(())
+            }
+            McpAction::Enable { name, scope } => {
+                let path = config_file::set_disabled(scope, &name, false)?;
+                println!("Enabled MCP server '{name}' in {}", path.display());
+                Ok(())
+            }
+            McpAction::Disable { name, scope } => {
+                let path = config_file::set_disabled(scope, &name, true)?;
+                println!("Disabled MCP server '{name}' in {}", path.display());
+                Ok(())
+            }
+            McpAction::Import {
+                source,
+                from,
+                scope,
+                force,
+            } => cmd_mcp_import(source, from, scope, force),
+            McpAction::Call {
+                server,
+                tool,
+                json,
+                yes,
+            } => cmd_mcp_call(&server, &tool, &json, yes).await,
+            McpAction::Invoke {
+                server,
+                tool,
+                args,
+                structured,
+                schema,
+            } => cmd_mcp_invoke(&server, &tool, &args, structured, schema).await,
+            McpAction::Serve => {
+                // stdout belongs to the protocol; warnings go to stderr.
+                let layered = Settings::load(&overrides)?;
+                for warning in &layered.warnings {
+                    eprintln!("⚠ {warning}");
+                }
+                mcp::serve::run(layered.settings).await
+            }
         },
-    }
+        Some(Commands::Config { action }) => match action {
+            ConfigAction::Show { origin } => cmd_config_show(&overrides, origin),
+        },
+        Some(Commands::Doctor) => cmd_doctor(&overrides).await,
+        Some(Commands::Prompt { action }) => match action {
+            PromptAction::Show => cmd_prompt_show(&overrides).await,
+        },
+    };
+    mcp::remove_blob_dir();
+    result
 }
 
 async fn cmd_mcp_list() -> Result<()> {
     let mut manager = McpManager::load()?;
 
-    if manager.configs().is_empty() {
+    let pending = manager.pending_project_servers();
+    if manager.configs().is_empty() && pending.is_empty() {
         println!("No MCP servers configured.");
-        println!("Add servers to ~/.mash/mcp.json");
+        println!("Add one with `mash mcp add <name> -- <command> [args]`");
         return Ok(());
     }
 
     println!("MCP Servers:\n");
 
+    for name in &pending {
+        println!(
+            "  {name}  [project]  — awaiting approval (start mash in this project to review it)"
+        );
+    }
     let mut names: Vec<String> = manager.configs().keys().cloned().collect();
     names.sort();
 
     for name in &names {
         let config = manager.configs()[name].clone();
-        let cmd_line = if config.args.is_empty() {
-            config.command.clone()
-        } else {
-            format!("{} {}", config.command, config.args.join(" "))
-        };
+        let cmd_line = config.describe();
 
         print!("  {name}  [{cmd_line}]");
 
@@ -72,22 +284,312 @@ async fn cmd_mcp_list() -> Result<()> {
             continue;
         }
 
-        match timeout(Duration::from_secs(30), manager.connect(name)).await {
-            Ok(Ok(())) => {
-                let count = manager
-                    .get_client(name)
-                    .map(|c| c.tool_count())
-                    .unwrap_or(0);
-                println!("  — ✓ connected ({count} tools)");
+        if timeout(Duration::from_secs(30), manager.connect(name))
+            .await
+            .is_err()
+        {
+            println!("  — ✗ timeout");
+            continue;
+        }
+        let health = manager.health(name).map(|h| h.label()).unwrap_or_default();
+        match manager.get_client(name) {
+            Some(client) => println!("  — {health} ({} tools)", client.tool_count()),
+            None => println!("  — {health}"),
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_mcp_add(
+    name: &str,
+    command: Vec<String>,
+    url: Option<String>,
+    transport: Option<String>,
+    env: &[String],
+    headers: &[String],
+    scope: McpScope,
+) -> Result<()> {
+    let mut entry = serde_json::Map::new();
+    match (url, command.split_first()) {
+        (Some(url), _) => {
+            if let Some(transport) = transport {
+                if transport != "http" && transport != "sse" {
+                    bail!("--type must be http or sse, got '{transport}'");
+                }
+                entry.insert("type".into(), json!(transport));
+            }
+            entry.insert("url".into(), json!(url));
+            if !headers.is_empty() {
+                entry.insert("headers".into(), json!(parse_pairs(headers, "--header")?));
+            }
+        }
+        (None, Some((program, args))) => {
+            entry.insert("command".into(), json!(program));
+            if !args.is_empty() {
+                entry.insert("args".into(), json!(args));
+            }
+        }
+        (None, None) => bail!("give the server command after `--`, or --url"),
+    }
+    if !env.is_empty() {
+        entry.insert("env".into(), json!(parse_pairs(env, "--env")?));
+    }
+
+    let path = config_file::add_server(scope, name, Value::Object(entry), false)?;
+    println!("Added MCP server '{name}' to {}", path.display());
+    Ok(())
+}
+
+/// Parse repeated `KEY=VALUE` options.
+fn parse_pairs(pairs: &[String], option: &str) -> Result<BTreeMap<String, String>> {
+    pairs
+        .iter()
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+            _ => bail!("{option} expects KEY=VALUE, got '{pair}'"),
+        })
+        .collect()
+}
+
+fn cmd_mcp_import(
+    source: ImportSource,
+    from: Option<PathBuf>,
+    scope: McpScope,
+    force: bool,
+) -> Result<()> {
+    let servers = config_file::import_servers(source, from)?;
+    if servers.is_empty() {
+        println!("No MCP servers found to import.");
+        return Ok(());
+    }
+    // Only the target scope matters: a server of the same name in the other
+    // scope is left alone, and add_server refuses to overwrite one in this scope.
+    let mut skipped = false;
+    for (name, entry) in servers {
+        match config_file::add_server(scope, &name, entry, force) {
+            Ok(path) => println!("  ✓ {name} → {}", path.display()),
+            Err(e) => {
+                println!("  ✗ {name}: {e}");
+                skipped = true;
             }
-            Ok(Err(e)) => println!("  — ✗ {e}"),
-            Err(_) => println!("  — ✗ timeout"),
         }
     }
+    if skipped && !force {
+        println!("Use --force to replace servers that already exist.");
+    }
+    Ok(())
+}
 
+async fn cmd_mcp_call(server: &str, tool: &str, arguments: &str, yes: bool) -> Result<()> {
+    let arguments: Value =
+        serde_json::from_str(arguments).map_err(|e| anyhow::anyhow!("--json: {e}"))?;
+    let mut manager = McpManager::load()?;
+    if let Some(config) = manager.configs().get(server) {
+        let in_session = std::env::var_os(mcp::MCP_TOKEN_ENV).is_some();
+        let interactive = std::io::stdin().is_terminal();
+        config.check_direct_call(tool, yes, in_session, interactive)?;
+    }
+    manager.connect(server).await?;
+    let client = manager.get_client(server).unwrap();
+
+    let Some(found) = client.tools().into_iter().find(|t| t.name == tool) else {
+        bail!("MCP server '{server}' has no tool '{tool}'");
+    };
+    let errors = mcp::schema::validate(&found.input_schema, &arguments);
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("  {}: {}", error.pointer, error.message);
+        }
+        bail!("arguments do not match the input schema of '{tool}'");
+    }
+
+    let result = client.call_tool(tool, arguments).await?;
+    if result.is_error {
+        bail!("{tool} failed: {}", result.text);
+    }
+    println!("{}", result.text);
     Ok(())
 }
 
+/// Exit codes of `mash mcp invoke`, as documented in the system prompt.
+const INVOKE_TOOL_FAILED: i32 = 1;
+const INVOKE_BAD_ARGUMENTS: i32 = 2;
+const INVOKE_REFUSED: i32 = 3;
+const INVOKE_UNAVAILABLE: i32 = 4;
+const INVOKE_NOT_FOUND: i32 = 5;
+
+async fn cmd_mcp_invoke(
+    server: &str,
+    tool: &str,
+    items: &[String],
+    structured: bool,
+    schema: bool,
+) -> Result<()> {
+    let bridge: mcp::BridgeAddr = match std::env::var(mcp::MCP_BRIDGE_ENV) {
+        Ok(addr) => addr
+            .parse()
+            .unwrap_or_else(|e: String| invoke_exit(INVOKE_UNAVAILABLE, &e)),
+        Err(_) => invoke_exit(
+            INVOKE_UNAVAILABLE,
+            &format!(
+                "{} is not set: `mash mcp invoke` runs from bash inside a mash session; use `mash mcp call` outside one",
+                mcp::MCP_BRIDGE_ENV
+            ),
+        ),
+    };
+    let token = std::env::var(mcp::MCP_TOKEN_ENV).unwrap_or_default();
+    let mut builder = reqwest::Client::builder();
+    if let mcp::BridgeAddr::Unix(path) = &bridge {
+        builder = builder.unix_socket(path.clone());
+    }
+    let client = builder.build()?;
+    let base = bridge.base_url();
+
+    let request = if schema {
+        // Names are path segments here, so `/`, `?` or `#` in them must be escaped.
+        let mut url = reqwest::Url::parse(&base)?;
+        url.path_segments_mut()
+            .map_err(|()| anyhow::anyhow!("bridge URL {base} cannot have a path"))?
+            .extend(["mcp", "tools", server, tool]);
+        client.get(url)
+    } else {
+        let arguments =
+            invoke_arguments(items).unwrap_or_else(|e| invoke_exit(INVOKE_BAD_ARGUMENTS, &e));
+        let query = if structured { "?structured=true" } else { "" };
+        client
+            .post(format!("{base}/mcp/call{query}"))
+            .json(&json!({ "server": server, "tool": tool, "arguments": arguments }))
+    };
+    let response = match request.bearer_auth(token).send().await {
+        Ok(response) => response,
+        Err(e) => invoke_exit(
+            INVOKE_UNAVAILABLE,
+            &format!("cannot reach the MCP bridge at {bridge}: {e}"),
+        ),
+    };
+    let status = response.status();
+    let body = response.text().await?;
+    let parsed: Option<Value> = serde_json::from_str(&body).ok();
+
+    if status.is_success() {
+        match parsed {
+            Some(value) => println!("{}", serde_json::to_string_pretty(&value)?),
+            None => println!("{}", body.trim_end_matches('\n')),
+        }
+        return Ok(());
+    }
+    match (status.as_u16(), parsed) {
+        (400, Some(envelope)) if envelope["errors"].is_array() => {
+            eprintln!("invalid arguments for {server}/{tool}:");
+            for error in envelope["errors"].as_array().into_iter().flatten() {
+                let pointer = error["pointer"].as_str().unwrap_or_default();
+                let message = error["message"].as_str().unwrap_or_default();
+                eprintln!(
+                    "  {}: {message}",
+                    if pointer.is_empty() { "/" } else { pointer }
+                );
+            }
+            invoke_exit(
+                INVOKE_BAD_ARGUMENTS,
+                &format!("see `mash mcp invoke --schema {server} {tool}`"),
+            )
+        }
+        (422, Some(envelope)) => invoke_exit(
+            INVOKE_TOOL_FAILED,
+            envelope["error"].as_str().unwrap_or(&body),
+        ),
+        (403, _) => invoke_exit(INVOKE_REFUSED, &body),
+        (404, _) => invoke_exit(INVOKE_NOT_FOUND, &body),
+        _ => invoke_exit(INVOKE_UNAVAILABLE, &format!("{status}: {body}")),
+    }
+}
+
+/// Tool arguments from HTTPie-style items: `key=value` is a string and
+/// `key:=<json>` any JSON value. Without items, a JSON object on stdin (none means `{}`).
+fn invoke_arguments(items: &[String]) -> Result<Value, String> {
+    if items.is_empty() {
+        let mut input = String::new();
+        if !std::io::stdin().is_terminal() {
+            std::io::stdin()
+                .read_to_string(&mut input)
+                .map_err(|e| format!("cannot read arguments from stdin: {e}"))?;
+        }
+        if input.trim().is_empty() {
+            return Ok(json!({}));
+        }
+        return match serde_json::from_str(&input) {
+            Ok(object @ Value::Object(_)) => Ok(object),
+            Ok(_) => Err("arguments on stdin must be a JSON object".to_string()),
+            Err(e) => Err(format!("arguments on stdin are not valid JSON: {e}")),
+        };
+    }
+
+    let mut arguments = serde_json::Map::new();
+    for item in items {
+        let parsed = match item.split_once('=') {
+            Some((key, raw)) => match key.strip_suffix(':') {
+                Some(key) => serde_json::from_str(raw)
+                    .map(|value| (key, value))
+                    .map_err(|e| format!("'{item}': the value after := is not valid JSON ({e})")),
+                None => Ok((key, Value::String(raw.to_string()))),
+            },
+            None => Err(format!("'{item}': expected key=value or key:=<json>")),
+        };
+        match parsed? {
+            ("", _) => return Err(format!("'{item}': missing key")),
+            (key, value) => arguments.insert(key.to_string(), value),
+        };
+    }
+    Ok(Value::Object(arguments))
+}
+
+/// Print `message` to stderr and exit with one of the `INVOKE_*` codes.
+fn invoke_exit(code: i32, message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(code)
+}
+
+/// Lines printed from the end of the log before `--follow` takes over.
+const LOG_TAIL_LINES: usize = 100;
+
+async fn cmd_mcp_logs(name: &str, follow: bool) -> Result<()> {
+    let path = mcp::logs::log_path(name)?;
+    if !path.exists() {
+        anyhow::bail!(
+            "no log for MCP server '{name}' ({} does not exist)",
+            path.display()
+        );
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    for line in &lines[lines.len().saturating_sub(LOG_TAIL_LINES)..] {
+        println!("{line}");
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    // Poll for growth; a shrinking file means it was rotated, so start over.
+    let mut offset = content.len() as u64;
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size < offset {
+            offset = 0;
+        }
+        if size > offset {
+            let mut file = std::fs::File::open(&path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut chunk = String::new();
+            offset += file.read_to_string(&mut chunk)? as u64;
+            print!("{chunk}");
+            std::io::stdout().flush()?;
+        }
+    }
+}
+
 async fn cmd_mcp_tools(name: &str) -> Result<()> {
     let mut manager = McpManager::load()?;
 
@@ -159,3 +661,365 @@ async fn cmd_mcp_tools(name: &str) -> Result<()> {
 
     Ok(())
 }
+
+async fn cmd_prompt_show(overrides: &CliOverrides) -> Result<()> {
+    let layered = Settings::load(overrides)?;
+    for warning in &layered.warnings {
+        eprintln!("⚠ {warning}");
+    }
+    let settings = layered.settings;
+    let mut manager = McpManager::load()?;
+
+    // Lazy servers stay unstarted, as in a fresh session.
+    let mut names: Vec<String> = manager
+        .configs()
+        .iter()
+        .filter(|(_, c)| !c.disabled && !c.lazy)
+        .map(|(n, _)| n.clone())
+        .collect();
+    names.sort();
+
+    for name in &names {
+        match timeout(Duration::from_secs(30), manager.connect(name)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("MCP: {name} — {e}"),
+            Err(_) => eprintln!("MCP: {name} — timeout"),
+        }
+    }
+
+    let bridge = mcp::expected_bridge_addr()?;
+    let task_file = tasks::new_task_file_path()?;
+    let skills = skills::scan_skills();
+    let instruction_files = instructions::discover_instructions();
+
+    let sections = prompt::prompt_sections(
+        &manager,
+        &bridge,
+        &settings,
+        &task_file,
+        &skills,
+        &instruction_files,
+    );
+    let full: String = sections.iter().map(|(_, s)| s.as_str()).collect();
+    println!("{full}");
+
+    // The report goes to stderr so the prompt itself can still be piped.
+    let threshold = settings.mcp_compact_threshold();
+    let tool_count = manager.tool_count();
+    let mode = if tool_count > threshold {
+        "compact"
+    } else {
+        "full"
+    };
+    eprintln!("Prompt size (tokens estimated):");
+    for (kind, section) in &sections {
+        let detail = match kind {
+            PromptSection::Mcp if !section.is_empty() => {
+                format!("  ({mode}: {tool_count} tools, compact above {threshold})")
+            }
+            _ => String::new(),
+        };
+        eprintln!(
+            "  {:<14}{:>8} chars  ~{:>6} tokens{detail}",
+            kind.label(),
+            section.chars().count(),
+            prompt::estimate_tokens(section)
+        );
+    }
+    eprintln!(
+        "  {:<14}{:>8} chars  ~{:>6} tokens",
+        "total",
+        full.chars().count(),
+        prompt::estimate_tokens(&full)
+    );
+    Ok(())
+}
+
+fn cmd_config_show(overrides: &CliOverrides, show_origin: bool) -> Result<()> {
+    let layered = Settings::load(overrides)?;
+    let settings = &layered.settings;
+
+    if show_origin {
+        println!("Layers (lowest precedence first):");
+        println!("  default");
+        for path in settings_paths() {
+            let state = if path.exists() { "" } else { "  (missing)" };
+            println!("  {}{state}", path.display());
+        }
+        println!("  env");
+        println!("  cli\n");
+    }
+
+    let mut entries: Vec<(String, String)> = vec![
+        (
+            "model_provider".to_string(),
+            settings.model_provider.clone(),
+        ),
+        ("model".to_string(), settings.model.clone()),
+        (
+            "max_tokens".to_string(),
+            settings
+                .max_tokens
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+        ),
+    ];
+    for provider in &settings.model_providers {
+        let prefix = format!("model_providers.{}", provider.name);
+        entries.push((format!("{prefix}.base_url"), provider.base_url.clone()));
+        entries.push((format!("{prefix}.api_key"), mask_secret(&provider.api_key)));
+        if let Some(command) = &provider.api_key_command {
+            entries.push((format!("{prefix}.api_key_command"), command.clone()));
+        }
+    }
+    for (i, target) in settings.fallback.iter().enumerate() {
+        entries.push((
+            format!("fallback[{i}]"),
+            format!("{}/{}", target.provider, target.model),
+        ));
+    }
+    if let Some(retries) = settings.max_retries {
+        entries.push(("max_retries".to_string(), retries.to_string()));
+    }
+    if let Some(secs) = settings.failover_cooldown_secs {
+        entries.push(("failover_cooldown_secs".to_string(), secs.to_string()));
+    }
+    if let Some(threshold) = settings.mcp_compact_threshold {
+        entries.push(("mcp_compact_threshold".to_string(), threshold.to_string()));
+    }
+    for (kind, patterns) in [
+        ("allow", &settings.permissions.allow),
+        ("deny", &settings.permissions.deny),
+    ] {
+        for pattern in patterns {
+            entries.push((format!("permissions.{kind}.{pattern}"), String::new()));
+        }
+    }
+
+    for (key, value) in entries {
+        let line = if value.is_empty() {
+            key.clone()
+        } else {
+            format!("{key} = {value}")
+        };
+        // List entries such as `fallback[0]` share the origin of the whole list.
+        let origin_key = key.split('[').next().unwrap_or(&key);
+        match layered.origins.get(origin_key).filter(|_| show_origin) {
+            Some(origin) => println!("{line}  ({origin})"),
+            None => println!("{line}"),
+        }
+    }
+    Ok(())
+}
+
+/// Keep only the first and last few characters of a secret for display.
+/// `${VAR}` references are shown as written since they hold no secret.
+fn mask_secret(secret: &str) -> String {
+    if secret.starts_with("${") && secret.ends_with('}') {
+        return secret.to_string();
+    }
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{head}…{tail}")
+}
+
+async fn cmd_doctor(overrides: &CliOverrides) -> Result<()> {
+    let mut problems = 0usize;
+
+    println!("Settings:");
+    for path in settings_paths() {
+        let state = if path.exists() { "found" } else { "missing" };
+        println!("  {}  — {state}", path.display());
+    }
+    let layered = match Settings::load(overrides) {
+        Ok(layered) => layered,
+        Err(e) => {
+            println!("  ✗ {e:#}");
+            bail!("1 problem found; fix settings before the remaining checks can run");
+        }
+    };
+    for warning in &layered.warnings {
+        println!("  ⚠ {warning}");
+    }
+
+    println!("\nProvider:");
+    match ApiConfig::from_settings(&layered.settings) {
+        Ok(mut config) => {
+            println!(
+                "  ✓ {} · {} · {}",
+                layered.settings.model_provider, config.model, config.base_url
+            );
+            config.max_tokens = 16;
+            let client = AnthropicClient::new(config, String::new()).with_policy(FailoverPolicy {
+                max_retries: 0,
+                ..FailoverPolicy::default()
+            });
+            let probe = [Message {
+                role: "user".to_string(),
+                content: MessageContent::Text("ping".to_string()),
+            }];
+            match timeout(Duration::from_secs(30), client.send(&probe, &[])).await {
+                Ok(Ok(_)) => println!("  ✓ test request succeeded"),
+                Ok(Err(e)) => {
+                    problems += 1;
+                    println!("  ✗ test request failed: {e}");
+                }
+                Err(_) => {
+                    problems += 1;
+                    println!("  ✗ test request timed out");
+                }
+            }
+        }
+        Err(e) => {
+            problems += 1;
+            println!("  ✗ {e:#}");
+        }
+    }
+
+    let (fallbacks, fallback_warnings) = layered.settings.fallback_configs();
+    for config in &fallbacks {
+        println!("  ✓ fallback {}", config.label());
+    }
+    for warning in &fallback_warnings {
+        problems += 1;
+        println!("  ✗ {warning}");
+    }
+
+    println!("\nMCP servers:");
+    match McpManager::load() {
+        Ok(mut manager) => {
+            for warning in manager.warnings() {
+                println!("  ⚠ {warning}");
+            }
+            let mut names: Vec<String> = manager.configs().keys().cloned().collect();
+            names.sort();
+            if names.is_empty() {
+                println!("  (none configured)");
+            }
+            for name in &names {
+                if manager.configs()[name].disabled {
+                    println!("  - {name}  — disabled");
+                    continue;
+                }
+                match timeout(Duration::from_secs(30), manager.connect(name)).await {
+                    Ok(Ok(())) => {
+                        let count = manager
+                            .get_client(name)
+                            .map(|c| c.tool_count())
+                            .unwrap_or(0);
+                        println!("  ✓ {name}  ({count} tools)");
+                    }
+                    Ok(Err(e)) => {
+                        problems += 1;
+                        println!("  ✗ {name}  — {e:#}");
+                    }
+                    Err(_) => {
+                        problems += 1;
+                        println!("  ✗ {name}  — timeout");
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            problems += 1;
+            println!("  ✗ {e:#}");
+        }
+    }
+
+    match mcp::bind_bridge().await {
+        Ok((_, mcp::BridgeAddr::Tcp(addr))) if addr.port() != mcp::DEFAULT_MCP_HTTP_PORT => {
+            println!(
+                "  ✓ HTTP bridge: port {} busy, a session would use {addr}",
+                mcp::DEFAULT_MCP_HTTP_PORT
+            )
+        }
+        Ok((_, mcp::BridgeAddr::Unix(path))) => {
+            let _ = std::fs::remove_file(&path);
+            println!(
+                "  ✓ HTTP bridge: unix socket under {} is usable",
+                path.parent().unwrap_or(&path).display()
+            );
+        }
+        Ok((_, addr)) => println!("  ✓ HTTP bridge: {addr} is free"),
+        Err(e) => {
+            problems += 1;
+            println!("  ✗ HTTP bridge: {e:#}");
+        }
+    }
+
+    println!("\nSkills:");
+    let (found, skill_problems) = skills::scan_skills_with_problems();
+    println!("  {} skills loaded", found.len());
+    for problem in &skill_problems {
+        println!("  ⚠ {problem}");
+    }
+
+    if problems > 0 {
+        println!();
+        bail!("{problems} problem(s) found");
+    }
+    println!("\nNo problems found.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(items: &[&str]) -> Result<Value, String> {
+        let items: Vec<String> = items.iter().map(|s| s.to_string()).collect();
+        invoke_arguments(&items)
+    }
+
+    #[test]
+    fn invoke_arguments_strings_and_json() {
+        assert_eq!(
+            parse(&[
+                "path=src/main.rs",
+                "limit:=5",
+                "deep:=true",
+                "tags:=[\"a\"]",
+                "opts:={\"n\":1}"
+            ])
+            .unwrap(),
+            json!({ "path": "src/main.rs", "limit": 5, "deep": true, "tags": ["a"], "opts": { "n": 1 } })
+        );
+        // `key=value` is always a string, even when it looks like JSON.
+        assert_eq!(
+            parse(&["n=5", "empty="]).unwrap(),
+            json!({ "n": "5", "empty": "" })
+        );
+    }
+
+    #[test]
+    fn invoke_arguments_split_at_the_first_equals_sign() {
+        assert_eq!(
+            parse(&["query=a=b", "url=http://x/?q:=1", "expr:=\"x=y\""]).unwrap(),
+            json!({ "query": "a=b", "url": "http://x/?q:=1", "expr": "x=y" })
+        );
+    }
+
+    #[test]
+    fn invoke_arguments_later_duplicates_win() {
+        assert_eq!(parse(&["a=1", "a:=2"]).unwrap(), json!({ "a": 2 }));
+    }
+
+    #[test]
+    fn invoke_arguments_errors() {
+        for items in [
+            &["limit:=five"][..],
+            &["noequals"],
+            &["=value"],
+            &[":=1"],
+            &["ok=1", "bad:={"],
+        ] {
+            let error = parse(items).unwrap_err();
+            let last = items.last().unwrap();
+            assert!(error.contains(last), "{error} should name '{last}'");
+        }
+    }
+}
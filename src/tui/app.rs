@@ -51,6 +51,7 @@ pub fn App(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
 
     let app_ctx = hooks.use_context::<AppContext>();
     let ui_sender = app_ctx.ui_sender.clone();
+    let ui_ready = app_ctx.ui_ready.clone();
 
     // Output welcome header once.
     let mut header_rendered_clone = header_rendered;
@@ -68,6 +69,7 @@ pub fn App(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
     let stdout_msgs = stdout.clone();
     hooks.use_future(async move {
         let mut rx = ui_sender.subscribe();
+        ui_ready.notify_one();
         while let Ok(msg) = rx.recv().await {
             match msg {
                 AppMessage::UserMessage(text) => {
@@ -106,8 +108,15 @@ pub fn App(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                 AppMessage::AgentCompleted => {
                     stdout_msgs.println("");
                 }
+                AppMessage::Notice(text) => {
+                    stdout_msgs.println(format!("\x1b[90m{}\x1b[0m", text));
+                }
                 AppMessage::AgentTaskStarted => {}
                 AppMessage::TasksUpdated { .. } => {}
+                AppMessage::ModelChanged(_) => {}
+                AppMessage::McpProgress(_) => {}
+                AppMessage::McpStartup(_) => {}
+                AppMessage::McpApproval(_) => {}
             }
         }
     });
@@ -1,11 +1,12 @@
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::collections::HashMap;
 
 use iocraft::prelude::*;
-use tokio::sync::Mutex;
 
 use crate::core::agent::{self, AgentEvent};
-use crate::core::api::{AnthropicClient, Message, MessageContent};
+use crate::core::api::{Message, MessageContent};
+use crate::core::config::{ApiConfig, save_model_choice};
+use crate::core::instructions;
+use crate::core::mcp::McpPrompt;
 use crate::core::skills::SkillInfo;
 use crate::tui::{AppContext, AppMessage};
 
@@ -16,25 +17,74 @@ struct SlashCommand {
     description: String,
     /// true for built-in commands like /new, false for skill-based commands.
     builtin: bool,
+    /// Set for MCP prompt templates: the server and the prompt to expand.
+    mcp_prompt: Option<(String, McpPrompt)>,
 }
 
-/// Build the full list of slash commands from built-ins + scanned skills.
-fn build_commands(skills: &[SkillInfo]) -> Vec<SlashCommand> {
-    let mut cmds = vec![SlashCommand {
-        name: "new".to_string(),
-        description: "清空上下文，开始新对话".to_string(),
-        builtin: true,
-    }];
+/// Build the full list of slash commands from built-ins + scanned skills + MCP prompts.
+fn build_commands(skills: &[SkillInfo], mcp_prompts: &[(String, McpPrompt)]) -> Vec<SlashCommand> {
+    let mut cmds = vec![
+        SlashCommand {
+            name: "new".to_string(),
+            description: "清空上下文，开始新对话".to_string(),
+            builtin: true,
+            mcp_prompt: None,
+        },
+        SlashCommand {
+            name: "model".to_string(),
+            description: "列出模型；/model <provider/model> [--save] 切换".to_string(),
+            builtin: true,
+            mcp_prompt: None,
+        },
+        SlashCommand {
+            name: "mcp".to_string(),
+            description: "查看 MCP server 连接状态".to_string(),
+            builtin: true,
+            mcp_prompt: None,
+        },
+        SlashCommand {
+            name: "init".to_string(),
+            description: "分析当前仓库并起草 MASH.md 项目说明".to_string(),
+            builtin: true,
+            mcp_prompt: None,
+        },
+    ];
     for skill in skills {
         cmds.push(SlashCommand {
             name: skill.name.clone(),
             description: skill.description.clone(),
             builtin: false,
+            mcp_prompt: None,
+        });
+    }
+    for (server, prompt) in mcp_prompts {
+        cmds.push(SlashCommand {
+            name: format!("mcp__{server}__{}", prompt.name),
+            description: format!(
+                "[MCP {server}] {}",
+                prompt.description.as_deref().unwrap_or("")
+            ),
+            builtin: false,
+            mcp_prompt: Some((server.clone(), prompt.clone())),
         });
     }
     cmds
 }
 
+/// Match a typed `/mcp__server__prompt [args…]` line; returns server, prompt and args.
+fn find_mcp_prompt<'a>(
+    commands: &'a [SlashCommand],
+    text: &'a str,
+) -> Option<(&'a str, &'a McpPrompt, &'a str)> {
+    let (name, args) = text.split_once(' ').unwrap_or((text, ""));
+    let name = name.strip_prefix('/')?;
+    commands
+        .iter()
+        .find(|c| c.name == name)
+        .and_then(|c| c.mcp_prompt.as_ref())
+        .map(|(server, prompt)| (server.as_str(), prompt, args))
+}
+
 /// Filter commands by the query typed after `/`.
 fn filter_commands<'a>(commands: &'a [SlashCommand], query: &str) -> Vec<&'a SlashCommand> {
     if query.is_empty() {
@@ -53,21 +103,21 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
     let input_buf = hooks.use_state(String::new);
     let busy = hooks.use_state(|| false);
     let menu_index = hooks.use_state(|| 0usize);
+    let approval = hooks.use_state(|| Option::<String>::None);
     let (width, _) = hooks.use_terminal_size();
 
     let app_ctx = hooks.use_context::<AppContext>();
+    let ctx = (*app_ctx).clone();
     let ui_sender = app_ctx.ui_sender.clone();
-    let client = app_ctx.client.clone();
-    let tool_defs = app_ctx.tool_defs.clone();
     let messages = app_ctx.messages.clone();
     let pending_user_messages = app_ctx.pending_user_messages.clone();
-    let task_file = app_ctx.task_file.clone();
     let skills = app_ctx.skills.clone();
 
-    let all_commands = build_commands(&skills);
+    let all_commands = build_commands(&skills, &app_ctx.mcp_prompts.lock().unwrap());
 
     // Track busy state from broadcast messages.
     let mut busy_track = busy;
+    let mut approval_track = approval;
     let ui_sender_track = ui_sender.clone();
     hooks.use_future(async move {
         let mut rx = ui_sender_track.subscribe();
@@ -75,6 +125,7 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
             match msg {
                 AppMessage::AgentTaskStarted => busy_track.set(true),
                 AppMessage::AgentCompleted | AppMessage::AgentError(_) => busy_track.set(false),
+                AppMessage::McpApproval(call) => approval_track.set(call),
                 _ => {}
             }
         }
@@ -85,11 +136,8 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
         let mut input_buf = input_buf;
         let mut menu_index = menu_index;
         let ui_sender = ui_sender.clone();
-        let client = client.clone();
-        let tool_defs = tool_defs.clone();
         let messages = messages.clone();
         let pending_user_messages = pending_user_messages.clone();
-        let task_file = task_file.clone();
         let all_commands = all_commands.clone();
         move |event| {
             if let TerminalEvent::Key(key) = event {
@@ -97,6 +145,16 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                     return;
                 }
 
+                // y/n answers a pending MCP approval (tool call, sampling, project
+                // server), but only on an empty input so typing a word never does.
+                if let KeyCode::Char(c @ ('y' | 'Y' | 'n' | 'N')) = key.code
+                    && input_buf.read().is_empty()
+                    && let Some(decide) = ctx.mcp_approval.lock().unwrap().take()
+                {
+                    let _ = decide.send(c.eq_ignore_ascii_case(&'y'));
+                    return;
+                }
+
                 let buf_snapshot = input_buf.read().clone();
                 // Menu is active only when input is "/" optionally followed by a
                 // single word (no spaces). Once a space appears the user has moved
@@ -156,12 +214,47 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                                     tokio::spawn(async move {
                                         messages.lock().await.clear();
                                     });
+                                } else if cmd.builtin && cmd.name == "model" {
+                                    input_buf.set(String::new());
+                                    menu_index.set(0);
+                                    let _ = ui_sender
+                                        .send(AppMessage::UserMessage("/model".to_string()));
+                                    run_model_command("", &ctx);
+                                } else if cmd.builtin && cmd.name == "mcp" {
+                                    input_buf.set(String::new());
+                                    menu_index.set(0);
+                                    let _ =
+                                        ui_sender.send(AppMessage::UserMessage("/mcp".to_string()));
+                                    let ctx = ctx.clone();
+                                    tokio::spawn(async move { show_mcp_status(&ctx).await });
+                                } else if let Some((server, prompt)) = &cmd.mcp_prompt {
+                                    menu_index.set(0);
+                                    if prompt.arguments.is_empty() {
+                                        input_buf.set(String::new());
+                                        run_mcp_prompt(
+                                            format!("/{}", cmd.name),
+                                            server,
+                                            prompt,
+                                            "",
+                                            *busy.read(),
+                                            &ctx,
+                                        );
+                                    } else {
+                                        // Let the user type the arguments first.
+                                        input_buf.set(format!("/{} ", cmd.name));
+                                    }
                                 } else {
-                                    // Skill command: send as user message with / prefix
-                                    let text = format!("/{}", cmd.name);
+                                    // Skill command: send as user message with / prefix.
+                                    // /init expands into the repo-analysis prompt.
+                                    let display = format!("/{}", cmd.name);
+                                    let text = if cmd.builtin && cmd.name == "init" {
+                                        instructions::init_prompt()
+                                    } else {
+                                        display.clone()
+                                    };
                                     input_buf.set(String::new());
                                     menu_index.set(0);
-                                    let _ = ui_sender.send(AppMessage::UserMessage(text.clone()));
+                                    let _ = ui_sender.send(AppMessage::UserMessage(display));
                                     if *busy.read() {
                                         let pending = pending_user_messages.clone();
                                         tokio::spawn(async move {
@@ -169,15 +262,7 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                                         });
                                     } else {
                                         let _ = ui_sender.send(AppMessage::AgentTaskStarted);
-                                        spawn_agent_task(
-                                            text,
-                                            client.clone(),
-                                            tool_defs.clone(),
-                                            messages.clone(),
-                                            pending_user_messages.clone(),
-                                            ui_sender.clone(),
-                                            task_file.clone(),
-                                        );
+                                        spawn_agent_task(text, ctx.clone());
                                     }
                                 }
                             }
@@ -187,7 +272,23 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                             input_buf.set(buf);
                         } else {
                             let text = buf_snapshot.trim().to_string();
-                            if !text.is_empty() {
+                            if let Some(args) = text.strip_prefix("/model ") {
+                                input_buf.set(String::new());
+                                let _ = ui_sender.send(AppMessage::UserMessage(text.clone()));
+                                run_model_command(args, &ctx);
+                            } else if let Some((server, prompt, args)) =
+                                find_mcp_prompt(&all_commands, &text)
+                            {
+                                input_buf.set(String::new());
+                                run_mcp_prompt(
+                                    text.clone(),
+                                    server,
+                                    prompt,
+                                    args,
+                                    *busy.read(),
+                                    &ctx,
+                                );
+                            } else if !text.is_empty() {
                                 input_buf.set(String::new());
                                 let _ = ui_sender.send(AppMessage::UserMessage(text.clone()));
                                 if *busy.read() {
@@ -197,15 +298,7 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                                     });
                                 } else {
                                     let _ = ui_sender.send(AppMessage::AgentTaskStarted);
-                                    spawn_agent_task(
-                                        text,
-                                        client.clone(),
-                                        tool_defs.clone(),
-                                        messages.clone(),
-                                        pending_user_messages.clone(),
-                                        ui_sender.clone(),
-                                        task_file.clone(),
-                                    );
+                                    spawn_agent_task(text, ctx.clone());
                                 }
                             }
                         }
@@ -285,11 +378,30 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
 
     let input_width = if width > 6 { width - 4 } else { 76 };
 
+    let approval_line: Vec<AnyElement<'static>> = match approval.read().as_ref() {
+        Some(question) => vec![
+            element! {
+                Text(
+                    content: if input_buf.read().is_empty() {
+                        format!("⚠ {question} [y/n]")
+                    } else {
+                        format!("⚠ {question} [清空输入后按 y/n]")
+                    },
+                    color: Color::Yellow,
+                    weight: Weight::Bold,
+                )
+            }
+            .into_any(),
+        ],
+        None => Vec::new(),
+    };
+
     element! {
         View(
             flex_direction: FlexDirection::Column,
             width: input_width,
         ) {
+            #(approval_line)
             #(menu_items)
             View(
                 border_style: BorderStyle::Round,
@@ -303,15 +415,182 @@ pub fn InputSection(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
     }
 }
 
-fn spawn_agent_task(
-    input: String,
-    client: Arc<AnthropicClient>,
-    tool_defs: Arc<Vec<serde_json::Value>>,
-    messages: Arc<Mutex<Vec<Message>>>,
-    pending_user_messages: Arc<Mutex<Vec<String>>>,
-    sender: tokio::sync::broadcast::Sender<AppMessage>,
-    task_file: Arc<PathBuf>,
+/// `/model` lists the available models; `/model <choice> [--save]` switches the
+/// client to another provider/model while keeping the conversation.
+fn run_model_command(args: &str, ctx: &AppContext) {
+    let notice = |text: String| {
+        let _ = ctx.ui_sender.send(AppMessage::Notice(text));
+    };
+    let current = ctx.client.config();
+    let save = args.split_whitespace().any(|a| a == "--save");
+    let choice = args
+        .split_whitespace()
+        .filter(|a| *a != "--save")
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if choice.is_empty() {
+        let models = ctx.settings.available_models();
+        if models.is_empty() {
+            notice("settings.json 中没有配置 model_providers".to_string());
+            return;
+        }
+        notice("可用模型：".to_string());
+        for (provider, model) in models {
+            let marker = if provider == current.provider && model == current.model {
+                "*"
+            } else {
+                " "
+            };
+            notice(format!("  {marker} {provider}/{model}"));
+        }
+        notice("用 /model <provider/model> 切换，加 --save 写回 ~/.mash/settings.json".to_string());
+        return;
+    }
+
+    let (provider, model) = match ctx.settings.resolve_model_choice(&choice, &current) {
+        Ok(choice) => choice,
+        Err(e) => {
+            notice(format!("{e}；/model 查看可用模型"));
+            return;
+        }
+    };
+    match ApiConfig::for_model(&ctx.settings, &provider, &model) {
+        Ok(config) => {
+            let label = config.label();
+            ctx.client.set_config(config);
+            let _ = ctx.ui_sender.send(AppMessage::ModelChanged(label.clone()));
+            notice(format!("已切换到 {label}"));
+            if save {
+                match save_model_choice(&provider, &model) {
+                    Ok(path) => notice(format!("已保存到 {}", path.display())),
+                    Err(e) => notice(format!("保存失败：{e}")),
+                }
+            }
+        }
+        Err(e) => notice(format!("无法切换到 {provider}/{model}：{e}")),
+    }
+}
+
+/// `/mcp`: one line per configured server with its transport, health and tool count.
+async fn show_mcp_status(ctx: &AppContext) {
+    let notice = |text: String| {
+        let _ = ctx.ui_sender.send(AppMessage::Notice(text));
+    };
+    let mcp = ctx.mcp.lock().await;
+    let mut names: Vec<&String> = mcp.configs().keys().collect();
+    if names.is_empty() {
+        notice("没有配置 MCP server（~/.mash/mcp.json）".to_string());
+        return;
+    }
+    names.sort();
+    notice("MCP servers：".to_string());
+    for name in names {
+        let health = mcp
+            .health(name)
+            .map(|h| h.label())
+            .unwrap_or_else(|| "connecting".to_string());
+        let tools = match mcp.get_client(name) {
+            Some(client) => format!(" · {} tools", client.tool_count()),
+            None => String::new(),
+        };
+        notice(format!(
+            "  {name}  [{}]  {health}{tools}",
+            mcp.configs()[name].describe()
+        ));
+    }
+}
+
+/// Expand an MCP prompt template and send the result like a typed message.
+/// Arguments are positional, in the order the server declares them; the last
+/// one takes the rest of the line.
+fn run_mcp_prompt(
+    display: String,
+    server: &str,
+    prompt: &McpPrompt,
+    args: &str,
+    busy: bool,
+    ctx: &AppContext,
 ) {
+    let _ = ctx.ui_sender.send(AppMessage::UserMessage(display));
+    let mut values = HashMap::new();
+    let mut rest = args.trim();
+    for (i, arg) in prompt.arguments.iter().enumerate() {
+        if rest.is_empty() {
+            break;
+        }
+        let value = if i + 1 == prompt.arguments.len() {
+            std::mem::take(&mut rest)
+        } else {
+            let (value, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            rest = tail.trim_start();
+            value
+        };
+        values.insert(arg.name.clone(), value.to_string());
+    }
+
+    let missing: Vec<&str> = prompt
+        .arguments
+        .iter()
+        .filter(|a| a.required && !values.contains_key(&a.name))
+        .map(|a| a.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        let usage: Vec<String> = prompt
+            .arguments
+            .iter()
+            .map(|a| {
+                if a.required {
+                    format!("<{}>", a.name)
+                } else {
+                    format!("[{}]", a.name)
+                }
+            })
+            .collect();
+        let _ = ctx.ui_sender.send(AppMessage::Notice(format!(
+            "缺少参数 {}；用法：/mcp__{server}__{} {}",
+            missing.join(", "),
+            prompt.name,
+            usage.join(" ")
+        )));
+        return;
+    }
+
+    let ctx = ctx.clone();
+    let server = server.to_string();
+    let name = prompt.name.clone();
+    tokio::spawn(async move {
+        let client = ctx.mcp.lock().await.client_handle(&server);
+        let expanded = match client {
+            Some(client) => client.get_prompt(&name, &values).await,
+            None => Err(anyhow::anyhow!("MCP server '{server}' not connected")),
+        };
+        match expanded {
+            Ok(text) if busy => ctx.pending_user_messages.lock().await.push(text),
+            Ok(text) => {
+                let _ = ctx.ui_sender.send(AppMessage::AgentTaskStarted);
+                spawn_agent_task(text, ctx);
+            }
+            Err(e) => {
+                let _ = ctx
+                    .ui_sender
+                    .send(AppMessage::Notice(format!("MCP prompt 展开失败：{e}")));
+            }
+        }
+    });
+}
+
+fn spawn_agent_task(input: String, ctx: AppContext) {
+    let AppContext {
+        client,
+        tool_defs,
+        ui_sender: sender,
+        messages,
+        pending_user_messages,
+        task_file,
+        permissions,
+        ..
+    } = ctx;
     tokio::spawn(async move {
         // Push user message into shared history before starting agent loop.
         messages.lock().await.push(Message {
@@ -346,6 +625,7 @@ fn spawn_agent_task(
             tx,
             &task_file,
             &pending_user_messages,
+            &permissions,
         )
         .await;
         let _ = forwarder.await;
@@ -3,7 +3,8 @@ use std::time::Duration;
 
 use crate::tui::{AppContext, AppMessage};
 
-/// Animated status line: "思考中" + task progress bar, and below it the live task file content.
+/// Animated status line: "思考中" + active model + task progress bar + MCP call
+/// progress + MCP servers still starting, and below it the live task file content.
 #[component]
 pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
     let is_processing = hooks.use_state(|| false);
@@ -11,15 +12,22 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
     let tick = hooks.use_state(|| 0u64);
     let task_summary = hooks.use_state(|| Option::<(usize, usize)>::None);
     let task_content = hooks.use_state(|| Option::<String>::None);
+    let mcp_progress = hooks.use_state(|| Option::<String>::None);
+    let mcp_starting = hooks.use_state(|| 0usize);
 
     let app_ctx = hooks.use_context::<AppContext>();
     let ui_sender = app_ctx.ui_sender.clone();
     let task_file = app_ctx.task_file.clone();
+    let initial_model = app_ctx.client.config().label();
+    let model_label = hooks.use_state(move || initial_model);
 
     // Subscribe to agent lifecycle; refresh task file on start/complete/task-update.
     let mut is_proc = is_processing;
     let mut task_sum = task_summary;
     let mut task_content_ref = task_content;
+    let mut model_ref = model_label;
+    let mut progress_ref = mcp_progress;
+    let mut starting_ref = mcp_starting;
     let task_file_ref = task_file.clone();
     hooks.use_future(async move {
         let mut rx = ui_sender.subscribe();
@@ -27,6 +35,7 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
             match msg {
                 AppMessage::AgentTaskStarted => {
                     is_proc.set(true);
+                    progress_ref.set(None);
                     if let Some(s) = crate::core::tasks::read_task_content(&task_file_ref)
                         && s.contains("- [")
                     {
@@ -35,6 +44,7 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                 }
                 AppMessage::AgentCompleted | AppMessage::AgentError(_) => {
                     is_proc.set(false);
+                    progress_ref.set(None);
                     if let Some(s) = crate::core::tasks::read_task_content(&task_file_ref)
                         && s.contains("- [")
                     {
@@ -49,6 +59,11 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
                         task_content_ref.set(Some(s));
                     }
                 }
+                AppMessage::ModelChanged(label) => model_ref.set(label),
+                // A tool result means the call that reported progress has finished.
+                AppMessage::ToolResult { .. } => progress_ref.set(None),
+                AppMessage::McpProgress(text) => progress_ref.set(Some(text)),
+                AppMessage::McpStartup(pending) => starting_ref.set(pending),
                 _ => {}
             }
         }
@@ -92,15 +107,25 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
     let has_tasks = task_summary.read().is_some();
     let is_proc = *is_processing.read();
 
+    let model = model_label.read().clone();
+    let starting = match *mcp_starting.read() {
+        0 => String::new(),
+        n => format!(" · MCP 启动中（{n}）"),
+    };
+
     if !is_proc && !has_tasks {
-        return element! { View {} };
+        return element! {
+            View(padding_left: 1) {
+                Text(content: format!("◆ {model}{starting}"), color: Color::DarkGrey)
+            }
+        };
     }
 
     let spinners = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
     let idx = (*tick.read() % spinners.len() as u64) as usize;
 
     // Build task progress text.
-    let task_text = match *task_summary.read() {
+    let mut task_text = match *task_summary.read() {
         Some((done, total)) => {
             let bar_len = 10usize;
             let filled = (done * bar_len).checked_div(total).unwrap_or(0);
@@ -109,6 +134,10 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
         }
         None => String::new(),
     };
+    if is_proc && let Some(progress) = mcp_progress.read().as_ref() {
+        task_text.push_str(&format!(" ┃ ⋯ {progress}"));
+    }
+    task_text.push_str(&starting);
 
     // Task file content below status: monitor ~/.mash/tasks/[project]_[time].md
     let task_body = task_content.read().clone();
@@ -119,8 +148,8 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
         if is_proc {
             let secs = *elapsed.read();
             let text = format!(
-                "{} 思考中… ({}s · esc 中断){}",
-                spinners[idx], secs, task_text
+                "{} 思考中… ({}s · {} · esc 中断){}",
+                spinners[idx], secs, model, task_text
             );
             element! {
                 View(margin_bottom: 1, flex_direction: FlexDirection::Column, align_items: AlignItems::Start) {
@@ -148,8 +177,8 @@ pub fn StatusLine(mut hooks: Hooks) -> impl Into<AnyElement<'static>> {
     } else if is_proc {
         let secs = *elapsed.read();
         let text = format!(
-            "{} 思考中… ({}s · esc 中断){}",
-            spinners[idx], secs, task_text
+            "{} 思考中… ({}s · {} · esc 中断){}",
+            spinners[idx], secs, model, task_text
         );
         element! {
             View(margin_bottom: 1, flex_direction: FlexDirection::Column, align_items: AlignItems::Start) {
@@ -2,16 +2,18 @@ pub mod app;
 pub mod components;
 pub mod pages;
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use iocraft::prelude::*;
-use tokio::sync::{Mutex, broadcast};
+use tokio::sync::{Mutex, Notify, broadcast, mpsc, oneshot};
 
-use crate::core::agent;
-use crate::core::api::{AnthropicClient, Message};
-use crate::core::config::ApiConfig;
-use crate::core::mcp::McpManager;
+use crate::core::api::{AnthropicClient, ClientEvent, Message};
+use crate::core::config::{ApiConfig, CliOverrides, Permissions, Settings};
+use crate::core::instructions::{self, InstructionFile};
+use crate::core::mcp::host::{ApprovalKind, ApprovalRequest, SamplingPolicy};
+use crate::core::mcp::{BridgeAddr, McpEvent, McpManager, McpPrompt};
+use crate::core::prompt;
 use crate::core::skills::{self, SkillInfo};
 use crate::core::tools;
 
@@ -20,12 +22,30 @@ use crate::core::tools;
 pub enum AppMessage {
     UserMessage(String),
     AssistantLine(String),
-    ToolCall { name: String, description: String },
-    ToolResult { preview: String },
+    ToolCall {
+        name: String,
+        description: String,
+    },
+    ToolResult {
+        preview: String,
+    },
     AgentTaskStarted,
     AgentCompleted,
     AgentError(String),
-    TasksUpdated { done: usize, total: usize },
+    TasksUpdated {
+        done: usize,
+        total: usize,
+    },
+    /// Informational line from mash itself (not the model), e.g. `/model` output.
+    Notice(String),
+    /// The active model changed; carries the new `provider/model` label.
+    ModelChanged(String),
+    /// Progress reported by an MCP server for a running call.
+    McpProgress(String),
+    /// Number of MCP servers still starting in the background.
+    McpStartup(usize),
+    /// An MCP tool call or sampling request awaits y/n (the question), or `None` once answered.
+    McpApproval(Option<String>),
 }
 
 /// Shared application context passed via ContextProvider.
@@ -40,57 +60,95 @@ pub struct AppContext {
     pub pending_user_messages: Arc<Mutex<Vec<String>>>,
     pub task_file: Arc<std::path::PathBuf>,
     pub skills: Arc<Vec<SkillInfo>>,
+    /// MCP prompt templates (server, prompt) offered as slash commands; grows
+    /// as servers finish starting.
+    pub mcp_prompts: Arc<StdMutex<Vec<(String, McpPrompt)>>>,
+    /// Signalled once the UI listens for messages, so no startup notice is lost.
+    pub ui_ready: Arc<Notify>,
+    /// Answer to the MCP tool call currently awaiting approval, if any.
+    pub mcp_approval: Arc<StdMutex<Option<oneshot::Sender<bool>>>>,
+    pub permissions: Arc<Permissions>,
+    pub settings: Arc<Settings>,
 }
 
-pub async fn run() -> Result<()> {
-    let config = ApiConfig::load();
+pub async fn run(cli: CliOverrides) -> Result<()> {
+    let layered = Settings::load(&cli)?;
+    for warning in &layered.warnings {
+        println!("  ⚠ {warning}");
+    }
+    let settings = layered.settings;
+    let config = ApiConfig::from_settings(&settings)?;
 
-    // Load and connect MCP servers
+    // MCP servers are connected in the background once the UI is up.
     let mut mcp = McpManager::load()?;
-    let configs = mcp.configs().clone();
-    let mut names: Vec<String> = configs
-        .iter()
-        .filter(|(_, c)| !c.disabled)
-        .map(|(n, _)| n.clone())
-        .collect();
-    names.sort();
-
-    for name in &names {
-        match mcp.connect(name).await {
-            Ok(()) => {
-                let count = mcp.get_client(name).map(|c| c.tool_count()).unwrap_or(0);
-                println!("  ✓ MCP: {name} ({count} tools)");
-            }
-            Err(e) => println!("  ✗ MCP: {name} — {e}"),
-        }
+    for warning in mcp.warnings() {
+        println!("  ⚠ {warning}");
     }
-
+    let (approvals_tx, approvals) = mpsc::unbounded_channel();
+    mcp.set_approvals(approvals_tx);
     let mcp = Arc::new(Mutex::new(mcp));
-    let mcp_http_port: u16 = std::env::var("MCP_HTTP_PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(31415);
-    let mcp_http_addr = std::net::SocketAddr::from(([127, 0, 0, 1], mcp_http_port));
+    let (listener, bridge) = crate::core::mcp::bind_bridge()
+        .await
+        .context("failed to start the MCP HTTP bridge")?;
+    println!("  ✓ MCP bridge: {bridge}");
+    crate::core::mcp::set_session_bridge(bridge.clone());
+    let (ui_sender, _) = broadcast::channel::<AppMessage>(256);
     let mcp_server = Arc::clone(&mcp);
+    let mcp_token = crate::core::mcp::init_session_token().to_string();
+    let bridge_notices = ui_sender.clone();
     tokio::spawn(async move {
-        let _ = crate::core::mcp::run_mcp_http_server(mcp_http_addr, mcp_server).await;
+        let notify = {
+            let notices = bridge_notices.clone();
+            move |notice| {
+                let _ = notices.send(AppMessage::Notice(notice));
+            }
+        };
+        if let Err(e) =
+            crate::core::mcp::run_mcp_http_server(listener, mcp_server, mcp_token, notify).await
+        {
+            let _ = bridge_notices.send(AppMessage::Notice(format!("✗ MCP bridge stopped: {e}")));
+        }
     });
-    let base_url = format!("http://127.0.0.1:{}", mcp_http_port);
     let task_file = crate::core::tasks::init_task_file()?;
     let skills = skills::scan_skills();
-    let system_prompt = format!(
-        "{}{}{}{}",
-        agent::SYSTEM_PROMPT,
-        crate::core::mcp::format_mcp_tools_for_prompt(&*mcp.lock().await, &base_url),
-        crate::core::tasks::format_task_prompt(&task_file),
-        skills::format_skills_for_prompt(&skills),
+    let instruction_files = instructions::discover_instructions();
+    for file in &instruction_files {
+        println!("  ✓ Instructions: {}", file.path.display());
+    }
+    let system_prompt = prompt::compose_system_prompt(
+        &*mcp.lock().await,
+        &bridge,
+        &settings,
+        &task_file,
+        &skills,
+        &instruction_files,
     );
-    let client = Arc::new(AnthropicClient::new(config, system_prompt));
-
-    let tool_defs = Arc::new(tools::definitions());
 
-    let (ui_sender, _) = broadcast::channel::<AppMessage>(256);
+    let (fallbacks, fallback_warnings) = settings.fallback_configs();
+    for warning in &fallback_warnings {
+        println!("  ⚠ {warning}");
+    }
+    let event_sender = ui_sender.clone();
+    let client = Arc::new(
+        AnthropicClient::new(config, system_prompt)
+            .with_fallbacks(fallbacks)
+            .with_policy(settings.failover_policy())
+            .with_event_handler(move |event| {
+                let (notice, label) = match event {
+                    ClientEvent::Failover { from, to, error } => {
+                        (format!("⚠ {from} 不可用（{error}），已切换到 {to}"), to)
+                    }
+                    ClientEvent::Restored { primary } => {
+                        (format!("✓ 已恢复到主模型 {primary}"), primary)
+                    }
+                };
+                let _ = event_sender.send(AppMessage::Notice(notice));
+                let _ = event_sender.send(AppMessage::ModelChanged(label));
+            }),
+    );
+    mcp.lock().await.set_sampler(Arc::clone(&client));
 
+    let tool_defs = Arc::new(tools::definitions());
     let ctx = AppContext {
         client,
         tool_defs,
@@ -100,7 +158,25 @@ pub async fn run() -> Result<()> {
         pending_user_messages: Arc::new(Mutex::new(Vec::new())),
         task_file: Arc::new(task_file),
         skills: Arc::new(skills),
+        mcp_prompts: Arc::new(StdMutex::new(Vec::new())),
+        ui_ready: Arc::new(Notify::new()),
+        mcp_approval: Arc::new(StdMutex::new(None)),
+        permissions: Arc::new(settings.permissions.clone()),
+        settings: Arc::new(settings),
     };
+    spawn_mcp_event_handler(ctx.clone(), bridge.clone(), instruction_files);
+    spawn_mcp_approval_handler(ctx.clone(), approvals);
+
+    let mcp_notices = ctx.ui_sender.clone();
+    let ui_ready = Arc::clone(&ctx.ui_ready);
+    let mcp_start = Arc::clone(&ctx.mcp);
+    tokio::spawn(async move {
+        ui_ready.notified().await;
+        crate::core::mcp::start_servers(mcp_start, move |notice| {
+            let _ = mcp_notices.send(AppMessage::Notice(notice));
+        })
+        .await;
+    });
 
     element! {
         ContextProvider(value: Context::owned(ctx)) {
@@ -110,5 +186,166 @@ pub async fn run() -> Result<()> {
     .render_loop()
     .await?;
 
+    if let BridgeAddr::Unix(path) = &bridge {
+        let _ = std::fs::remove_file(path);
+    }
     Ok(())
 }
+
+/// React to MCP server notifications: refetch tools and rebuild the system
+/// prompt on `tools/list_changed` or when a server (re)connects, and forward
+/// progress and startup state to the status line.
+fn spawn_mcp_event_handler(
+    ctx: AppContext,
+    bridge: BridgeAddr,
+    instruction_files: Vec<InstructionFile>,
+) {
+    tokio::spawn(async move {
+        let mut events = ctx.mcp.lock().await.subscribe();
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            match event {
+                McpEvent::ToolsChanged { server } => {
+                    let Some(client) = ctx.mcp.lock().await.client_handle(&server) else {
+                        continue;
+                    };
+                    let notice = match client.refresh_tools().await {
+                        Ok(count) => format!("↻ MCP: {server} 工具列表已更新（{count} tools）"),
+                        Err(e) => format!("⚠ MCP: {server} 刷新工具列表失败：{e}"),
+                    };
+                    rebuild_mcp_context(&ctx, &*ctx.mcp.lock().await, &bridge, &instruction_files);
+                    let _ = ctx.ui_sender.send(AppMessage::Notice(notice));
+                }
+                McpEvent::HealthChanged { server } => {
+                    let manager = ctx.mcp.lock().await;
+                    let _ = ctx
+                        .ui_sender
+                        .send(AppMessage::McpStartup(manager.starting_count()));
+                    if manager.is_connected(&server) {
+                        rebuild_mcp_context(&ctx, &manager, &bridge, &instruction_files);
+                    }
+                }
+                McpEvent::Progress { server, message } => {
+                    let _ = ctx
+                        .ui_sender
+                        .send(AppMessage::McpProgress(format!("{server}: {message}")));
+                }
+            }
+        }
+    });
+}
+
+/// Ask the user about each `approveTools` call and sampling request in turn;
+/// the input section answers through `ctx.mcp_approval` when y or n is pressed.
+fn spawn_mcp_approval_handler(
+    ctx: AppContext,
+    mut approvals: mpsc::UnboundedReceiver<ApprovalRequest>,
+) {
+    tokio::spawn(async move {
+        while let Some(request) = approvals.recv().await {
+            let (decide, decision) = oneshot::channel();
+            *ctx.mcp_approval.lock().unwrap() = Some(decide);
+            let (subject, question) = match &request.kind {
+                ApprovalKind::Tool { tool, arguments } => {
+                    let call = format!("MCP 调用 {}/{tool}", request.server);
+                    let question = format!(
+                        "允许调用 MCP 工具 {}/{tool} {}？",
+                        request.server,
+                        truncate(&arguments.to_string())
+                    );
+                    (call, question)
+                }
+                ApprovalKind::Sampling {
+                    system,
+                    messages,
+                    prompt,
+                    max_tokens,
+                } => {
+                    let system = if system.is_empty() {
+                        String::new()
+                    } else {
+                        format!("，system：{}", truncate(system))
+                    };
+                    (
+                        format!("MCP {} 的模型请求", request.server),
+                        format!(
+                            "允许 MCP {} 使用模型生成回复（{messages} 条消息，最多 {max_tokens} tokens{system}）：{}？",
+                            request.server,
+                            truncate(prompt)
+                        ),
+                    )
+                }
+                ApprovalKind::Server {
+                    command,
+                    sampling,
+                    replaces_user,
+                } => {
+                    let mut notes = Vec::new();
+                    if *replaces_user {
+                        notes.push("将替换用户配置中的同名 server");
+                    }
+                    if *sampling == SamplingPolicy::Allow {
+                        notes.push("可不经确认调用模型");
+                    }
+                    let notes = if notes.is_empty() {
+                        String::new()
+                    } else {
+                        format!("（{}）", notes.join("，"))
+                    };
+                    (
+                        format!("项目 MCP server {}", request.server),
+                        format!(
+                            "启动项目 .mash/mcp.json 中的 MCP server {}：{}{notes}？",
+                            request.server,
+                            truncate(command)
+                        ),
+                    )
+                }
+            };
+            let _ = ctx.ui_sender.send(AppMessage::McpApproval(Some(question)));
+
+            let approved = decision.await.unwrap_or(false);
+            let _ = request.reply.send(approved);
+            let _ = ctx.ui_sender.send(AppMessage::McpApproval(None));
+            let notice = if approved {
+                format!("✓ 已允许 {subject}")
+            } else {
+                format!("✗ 已拒绝 {subject}")
+            };
+            let _ = ctx.ui_sender.send(AppMessage::Notice(notice));
+        }
+    });
+}
+
+/// Shorten `text` to one line of at most 200 characters for the approval prompt.
+fn truncate(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() > 200 {
+        line.chars().take(200).collect::<String>() + "…"
+    } else {
+        line
+    }
+}
+
+/// Recompose the system prompt and the MCP slash commands from the current servers.
+fn rebuild_mcp_context(
+    ctx: &AppContext,
+    mcp: &McpManager,
+    bridge: &BridgeAddr,
+    instruction_files: &[InstructionFile],
+) {
+    let system_prompt = prompt::compose_system_prompt(
+        mcp,
+        bridge,
+        &ctx.settings,
+        &ctx.task_file,
+        &ctx.skills,
+        instruction_files,
+    );
+    ctx.client.set_system(system_prompt);
+    *ctx.mcp_prompts.lock().unwrap() = mcp.prompts();
+}
@@ -0,0 +1,260 @@
+//! Failover decisions of `AnthropicClient`, exercised against local stand-in servers.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::post};
+use mash::core::api::{AnthropicClient, ClientEvent, FailoverPolicy, Message, MessageContent};
+use mash::core::config::ApiConfig;
+use serde_json::{Value, json};
+
+/// A running stand-in server: its request counter, how many of the first requests
+/// fail (raise it to take the server down later), the last request body, and a
+/// config pointing at it.
+struct StandIn {
+    hits: Arc<AtomicUsize>,
+    failures: Arc<AtomicUsize>,
+    last_request: Arc<Mutex<Value>>,
+    config: ApiConfig,
+}
+
+#[derive(Clone)]
+struct StandInState {
+    hits: Arc<AtomicUsize>,
+    status: StatusCode,
+    failures: Arc<AtomicUsize>,
+    last_request: Arc<Mutex<Value>>,
+    reply: String,
+}
+
+/// Stand-in `/v1/messages`: fails the first `failures` requests with `status`,
+/// then answers with a text block naming the server.
+async fn stand_in_handler(
+    State(state): State<StandInState>,
+    Json(request): Json<Value>,
+) -> (StatusCode, Json<Value>) {
+    *state.last_request.lock().unwrap() = request;
+    let n = state.hits.fetch_add(1, Ordering::SeqCst);
+    if n < state.failures.load(Ordering::SeqCst) {
+        return (state.status, Json(json!({ "error": "stand-in failure" })));
+    }
+    (
+        StatusCode::OK,
+        Json(json!({
+            "content": [{ "type": "text", "text": state.reply }],
+            "stop_reason": "end_turn"
+        })),
+    )
+}
+
+async fn spawn_stand_in(name: &str, status: StatusCode, failures: usize) -> StandIn {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let failures = Arc::new(AtomicUsize::new(failures));
+    let last_request = Arc::new(Mutex::new(Value::Null));
+    let app = Router::new()
+        .route("/v1/messages", post(stand_in_handler))
+        .with_state(StandInState {
+            hits: Arc::clone(&hits),
+            status,
+            failures: Arc::clone(&failures),
+            last_request: Arc::clone(&last_request),
+            reply: name.to_string(),
+        });
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    StandIn {
+        hits,
+        failures,
+        last_request,
+        config: ApiConfig {
+            provider: name.to_string(),
+            base_url: format!("http://{addr}"),
+            api_key: "test".to_string(),
+            model: "m".to_string(),
+            max_tokens: 16,
+        },
+    }
+}
+
+fn policy(cooldown: Duration) -> FailoverPolicy {
+    FailoverPolicy {
+        max_retries: 1,
+        retry_delay: Duration::from_millis(1),
+        cooldown,
+    }
+}
+
+fn client(
+    primary: &StandIn,
+    fallbacks: &[&StandIn],
+    policy: FailoverPolicy,
+) -> (AnthropicClient, Arc<Mutex<Vec<ClientEvent>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    let client = AnthropicClient::new(primary.config.clone(), String::new())
+        .with_fallbacks(fallbacks.iter().map(|s| s.config.clone()).collect())
+        .with_policy(policy)
+        .with_event_handler(move |event| sink.lock().unwrap().push(event));
+    (client, events)
+}
+
+async fn ask(client: &AnthropicClient) -> anyhow::Result<String> {
+    let messages = [Message {
+        role: "user".to_string(),
+        content: MessageContent::Text("ping".to_string()),
+    }];
+    let response = client.send(&messages, &[]).await?;
+    match &response.content[0] {
+        mash::core::api::ContentBlock::Text { text } => Ok(text.clone()),
+        other => panic!("unexpected block: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn retries_primary_before_failing_over() {
+    let primary = spawn_stand_in("primary", StatusCode::SERVICE_UNAVAILABLE, 1).await;
+    let backup = spawn_stand_in("backup", StatusCode::OK, 0).await;
+    let (client, events) = client(&primary, &[&backup], policy(Duration::from_secs(60)));
+
+    assert_eq!(ask(&client).await.unwrap(), "primary");
+    assert_eq!(primary.hits.load(Ordering::SeqCst), 2);
+    assert_eq!(backup.hits.load(Ordering::SeqCst), 0);
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn fails_over_and_stays_on_fallback_during_cooldown() {
+    let primary = spawn_stand_in("primary", StatusCode::SERVICE_UNAVAILABLE, usize::MAX).await;
+    let backup = spawn_stand_in("backup", StatusCode::OK, 0).await;
+    let (client, events) = client(&primary, &[&backup], policy(Duration::from_secs(60)));
+
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    assert_eq!(primary.hits.load(Ordering::SeqCst), 2);
+    assert_eq!(client.active_config().provider, "backup");
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [ClientEvent::Failover { from, to, .. }] if from == "primary/m" && to == "backup/m"
+    ));
+
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    assert_eq!(primary.hits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn returns_to_primary_after_cooldown() {
+    let primary = spawn_stand_in("primary", StatusCode::TOO_MANY_REQUESTS, 2).await;
+    let backup = spawn_stand_in("backup", StatusCode::OK, 0).await;
+    let (client, events) = client(&primary, &[&backup], policy(Duration::from_millis(50)));
+
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    tokio::time::sleep(Duration::from_millis(80)).await;
+
+    assert_eq!(ask(&client).await.unwrap(), "primary");
+    assert_eq!(client.active_config().provider, "primary");
+    assert!(matches!(
+        events.lock().unwrap().last(),
+        Some(ClientEvent::Restored { primary }) if primary == "primary/m"
+    ));
+}
+
+#[tokio::test]
+async fn walks_the_whole_chain() {
+    let primary = spawn_stand_in("primary", StatusCode::BAD_GATEWAY, usize::MAX).await;
+    let second = spawn_stand_in("second", StatusCode::INTERNAL_SERVER_ERROR, usize::MAX).await;
+    let third = spawn_stand_in("third", StatusCode::OK, 0).await;
+    let (client, events) = client(
+        &primary,
+        &[&second, &third],
+        policy(Duration::from_secs(60)),
+    );
+
+    assert_eq!(ask(&client).await.unwrap(), "third");
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [ClientEvent::Failover { from, to, .. }] if from == "second/m" && to == "third/m"
+    ));
+}
+
+#[tokio::test]
+async fn failing_fallback_wraps_around_to_the_primary() {
+    let primary = spawn_stand_in("primary", StatusCode::SERVICE_UNAVAILABLE, 2).await;
+    let backup = spawn_stand_in("backup", StatusCode::SERVICE_UNAVAILABLE, 0).await;
+    let (client, events) = client(&primary, &[&backup], policy(Duration::from_secs(60)));
+
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    backup.failures.store(usize::MAX, Ordering::SeqCst);
+
+    // Still inside the cool-down, but the primary has recovered.
+    assert_eq!(ask(&client).await.unwrap(), "primary");
+    assert_eq!(client.active_config().provider, "primary");
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [ClientEvent::Failover { .. }, ClientEvent::Restored { primary }] if primary == "primary/m"
+    ));
+}
+
+#[tokio::test]
+async fn staying_on_the_same_provider_emits_nothing() {
+    let primary = spawn_stand_in("primary", StatusCode::SERVICE_UNAVAILABLE, 2).await;
+    let backup = spawn_stand_in("backup", StatusCode::OK, 0).await;
+    let (client, events) = client(&primary, &[&backup], policy(Duration::from_secs(60)));
+
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    assert_eq!(events.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn client_errors_do_not_fail_over() {
+    let primary = spawn_stand_in("primary", StatusCode::BAD_REQUEST, usize::MAX).await;
+    let backup = spawn_stand_in("backup", StatusCode::OK, 0).await;
+    let (client, events) = client(&primary, &[&backup], policy(Duration::from_secs(60)));
+
+    assert!(ask(&client).await.is_err());
+    assert_eq!(primary.hits.load(Ordering::SeqCst), 1);
+    assert_eq!(backup.hits.load(Ordering::SeqCst), 0);
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn all_providers_down_returns_last_error() {
+    let primary = spawn_stand_in("primary", StatusCode::SERVICE_UNAVAILABLE, usize::MAX).await;
+    let backup = spawn_stand_in("backup", StatusCode::SERVICE_UNAVAILABLE, usize::MAX).await;
+    let (client, _) = client(&primary, &[&backup], policy(Duration::from_secs(60)));
+
+    let err = ask(&client).await.unwrap_err();
+    assert!(err.to_string().contains("503"));
+    assert_eq!(client.active_config().provider, "primary");
+}
+
+#[tokio::test]
+async fn sampling_fails_over_with_its_own_system_prompt() {
+    let primary = spawn_stand_in("primary", StatusCode::SERVICE_UNAVAILABLE, usize::MAX).await;
+    let backup = spawn_stand_in("backup", StatusCode::OK, 0).await;
+    let (client, events) = client(&primary, &[&backup], policy(Duration::from_secs(60)));
+
+    let messages = [Message {
+        role: "user".to_string(),
+        content: MessageContent::Text("summarize".to_string()),
+    }];
+    let (_, config) = client.sample("SAMPLE", 8, &messages).await.unwrap();
+    assert_eq!(config.provider, "backup");
+    assert_eq!(config.max_tokens, 8);
+    let request = backup.last_request.lock().unwrap().clone();
+    assert_eq!(request["system"], "SAMPLE");
+    assert_eq!(request["max_tokens"], 8);
+    assert_eq!(events.lock().unwrap().len(), 1);
+
+    // The session's own requests keep their system prompt and limit.
+    assert_eq!(ask(&client).await.unwrap(), "backup");
+    let request = backup.last_request.lock().unwrap().clone();
+    assert_eq!(request["system"], "");
+    assert_eq!(request["max_tokens"], 16);
+}